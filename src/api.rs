@@ -0,0 +1,971 @@
+use crate::archive;
+use crate::auth::{self, Access};
+use crate::error::WarError;
+use crate::format::{FormatInput, WarFormat};
+use crate::history::Step;
+use crate::store::{Edit, WarStore};
+use crate::table;
+use crate::track::Track;
+use crate::validate::{self, Policy, Violation};
+use crate::war::{OverlayData, Penalty, Phase, Team, WarData};
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+/// Longest accepted player name.
+const MAX_NAME_LEN: usize = 32;
+
+/// Longest accepted penalty reason.
+const MAX_REASON_LEN: usize = 32;
+
+/// Largest penalty, or team penalty total, accepted either way; well past
+/// what any war scores.
+pub const MAX_PENALTY: i32 = 9_999;
+
+/// Reason logged when `PUT /penalties` moves a team's total.
+const ADJUSTMENT: &str = "adjustment";
+
+#[derive(Deserialize)]
+pub struct NewWar {
+    tag: String,
+    enemy_tag: String,
+    /// 12 race 6v6 when absent.
+    format: Option<FormatInput>,
+    roster: Option<Roster>,
+}
+
+/// Player names per team. Substitutes may push a team past the format's
+/// team size.
+#[derive(Deserialize)]
+pub struct Roster {
+    home: Vec<String>,
+    enemy: Vec<String>,
+}
+
+impl Roster {
+    fn check(&self) -> Result<(), WarError> {
+        let mut seen = HashSet::new();
+        for name in self.home.iter().chain(&self.enemy) {
+            if name.trim().is_empty() || name.len() > MAX_NAME_LEN {
+                return Err(WarError::BadRequest(format!(
+                    "player names must be 1 to {MAX_NAME_LEN} characters, got {name:?}"
+                )));
+            }
+            if !seen.insert(name) {
+                return Err(WarError::BadRequest(format!(
+                    "{name:?} is on the roster twice"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reply to creating a war. `token` is only present when the request claimed
+/// the channel; it is shown this once.
+#[derive(Serialize)]
+struct Created {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    #[serde(flatten)]
+    war: OverlayData,
+}
+
+/// A revision as listed, without the war itself.
+#[derive(Serialize)]
+struct RevisionSummary {
+    /// 1-based, oldest first.
+    revision: usize,
+    at: Option<u64>,
+    action: String,
+    races: usize,
+    score: i32,
+    enemy_score: i32,
+    /// The revision viewers currently see.
+    live: bool,
+}
+
+#[derive(Serialize)]
+struct NewToken {
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct RaceInput {
+    #[serde(flatten)]
+    result: RaceResult,
+    /// Abbreviation or full name. Editing a race without one keeps its
+    /// current track.
+    track: Option<Track>,
+}
+
+/// A race result as submitted: team totals, the home team's finishing
+/// positions, or rostered players' positions. Positions are scored with the
+/// war's points table.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum RaceResult {
+    Scores {
+        home_score: f64,
+        enemy_score: f64,
+    },
+    Positions {
+        positions: Positions,
+    },
+    /// Player name to position; needs every home player who raced, enemy
+    /// players are optional.
+    Players {
+        players: BTreeMap<String, u32>,
+    },
+}
+
+/// A race result reduced to what is stored.
+struct Scored {
+    home: f64,
+    enemy: f64,
+    /// Per rostered player, in roster order; `None` when the result names no
+    /// players, so points already recorded for the race stay.
+    players: Option<Vec<Option<u32>>>,
+}
+
+/// `[1, 3, 4, 7, 8, 12]` or `"1 3 4 7 8 12"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum Positions {
+    List(Vec<u32>),
+    Text(String),
+}
+
+impl RaceResult {
+    /// Points for this race in `war`.
+    fn score(&self, war: &WarData) -> Result<Scored, WarError> {
+        let (home, enemy) = match self {
+            RaceResult::Scores {
+                home_score,
+                enemy_score,
+            } => {
+                for score in [*home_score, *enemy_score] {
+                    if !score.is_finite() || score < 0.0 {
+                        return Err(WarError::BadRequest(format!(
+                            "scores must be non-negative numbers, got {score}"
+                        )));
+                    }
+                }
+                (*home_score, *enemy_score)
+            }
+            RaceResult::Positions { positions } => {
+                let positions = match positions {
+                    Positions::List(list) => list.clone(),
+                    Positions::Text(text) => text
+                        .split(|c: char| c.is_whitespace() || c == ',')
+                        .filter(|p| !p.is_empty())
+                        .map(|p| {
+                            p.parse().map_err(|_| {
+                                WarError::BadRequest(format!("{p:?} is not a position"))
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                };
+                war.format.score_positions(&positions)?
+            }
+            RaceResult::Players { players } => return score_players(war, players),
+        };
+        Ok(Scored {
+            home,
+            enemy,
+            players: None,
+        })
+    }
+}
+
+fn score_players(war: &WarData, players: &BTreeMap<String, u32>) -> Result<Scored, WarError> {
+    let mut home_positions = Vec::new();
+    let mut points = vec![None; war.roster.len()];
+    let mut taken = HashSet::new();
+    for (name, &position) in players {
+        let index = war
+            .roster
+            .iter()
+            .position(|player| player.name == *name)
+            .ok_or_else(|| WarError::BadRequest(format!("{name:?} is not on the roster")))?;
+        if !taken.insert(position) {
+            return Err(WarError::BadRequest(format!(
+                "position {position} is listed twice"
+            )));
+        }
+        points[index] = Some(war.format.points_for(position).ok_or_else(|| {
+            WarError::BadRequest(format!(
+                "position {position} is outside 1 to {}",
+                war.format.points.len()
+            ))
+        })?);
+        if war.roster[index].team == Team::Home {
+            home_positions.push(position);
+        }
+    }
+
+    let (home, enemy) = war.format.score_positions(&home_positions)?;
+    Ok(Scored {
+        home,
+        enemy,
+        players: Some(points),
+    })
+}
+
+/// Team totals to reach; the difference is logged as an adjustment.
+#[derive(Deserialize)]
+pub struct SetPenalties {
+    home_pen: Option<i32>,
+    enemy_pen: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct AddPenalty {
+    team: Team,
+    amount: i32,
+    reason: Option<String>,
+    /// Races played when it was given, from 1 to the current count;
+    /// defaults to the current count.
+    race: Option<u32>,
+}
+
+/// Apply `edit` to the channel's war, recording it as `action`, and answer
+/// with the result as overlays will see it.
+async fn apply(
+    store: &dyn WarStore,
+    channel_id: &str,
+    action: &str,
+    edit: Edit<'_>,
+) -> Result<HttpResponse> {
+    let war = store.update(channel_id, action, edit).await?;
+    written(store, channel_id, war).await
+}
+
+/// Archive the written war if it is over and answer with it as overlays will
+/// see it.
+async fn written(store: &dyn WarStore, channel_id: &str, war: WarData) -> Result<HttpResponse> {
+    archive::keep(store, channel_id, &war).await;
+    // The write went through, so report problems instead of failing.
+    Ok(HttpResponse::Ok().json(validate::overlay(war, Policy::Warn)?))
+}
+
+fn existing(war: Option<WarData>) -> Result<WarData, WarError> {
+    war.ok_or(WarError::NotFound)
+}
+
+/// Turn a 1-based race number from the URL into an index into `war`.
+/// Wars whose score lists disagree on the race count can't be edited by
+/// index until repaired.
+fn race_index(war: &WarData, race: usize) -> Result<usize, WarError> {
+    let mismatch = validate::check(war)
+        .into_iter()
+        .find(|violation| matches!(violation, Violation::LengthMismatch { .. }));
+    if let Some(violation) = mismatch {
+        return Err(WarError::Invalid(violation.to_string()));
+    }
+    if race == 0 || race > war.race_count() {
+        return Err(WarError::BadRequest(format!(
+            "race {race} does not exist, the war has {} races",
+            war.race_count()
+        )));
+    }
+    Ok(race - 1)
+}
+
+#[post("/api/{channel_id}")]
+async fn create_war(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    body: web::Json<NewWar>,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+    let body = body.into_inner();
+    let format = match body.format {
+        Some(format) => format.build()?,
+        None => WarFormat::default(),
+    };
+    if let Some(roster) = &body.roster {
+        roster.check()?;
+    }
+
+    let token = match access {
+        Access::Unclaimed => Some(claim(store.as_ref(), &channel_id).await?),
+        Access::Admin | Access::Owner => None,
+    };
+
+    let created = store
+        .update(&channel_id, "create war", &|current| match current {
+            Some(war) if war.phase() != Phase::Final => Err(WarError::Conflict(
+                "a war is already running on this channel".to_string(),
+            )),
+            _ => {
+                let mut war =
+                    WarData::new(body.tag.clone(), body.enemy_tag.clone(), format.clone());
+                if let Some(roster) = &body.roster {
+                    war.set_roster(&roster.home, &roster.enemy);
+                }
+                Ok(war)
+            }
+        })
+        .await;
+    let war = release_on_error(store.as_ref(), &channel_id, token.as_deref(), created).await?;
+
+    Ok(HttpResponse::Created().json(Created {
+        token,
+        war: validate::overlay(war, Policy::Warn)?,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    /// Preset like "6v6"; guessed from the table when absent.
+    format: Option<String>,
+}
+
+/// Create or replace the channel's war from a table in the table tool's text
+/// format.
+#[put("/api/{channel_id}/table")]
+async fn import_table(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    query: web::Query<ImportQuery>,
+    body: String,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+    let table = table::parse(&body)?;
+    Roster {
+        home: table.home.names(),
+        enemy: table.enemy.names(),
+    }
+    .check()?;
+    let format = match &query.format {
+        Some(name) => Some(FormatInput::Preset(name.clone()).build()?),
+        None => None,
+    };
+    let war = table.war(format)?;
+
+    let token = match access {
+        Access::Unclaimed => Some(claim(store.as_ref(), &channel_id).await?),
+        Access::Admin | Access::Owner => None,
+    };
+    let imported = store
+        .update(&channel_id, "import table", &|_| Ok(war.clone()))
+        .await;
+    let war = release_on_error(store.as_ref(), &channel_id, token.as_deref(), imported).await?;
+    archive::keep(store.as_ref(), &channel_id, &war).await;
+
+    Ok(HttpResponse::Ok().json(Created {
+        token,
+        war: validate::overlay(war, Policy::Warn)?,
+    }))
+}
+
+/// Give an unclaimed channel a token. Channels that already have a war
+/// (e.g. written by the bot) can only be claimed by an admin.
+async fn claim(store: &dyn WarStore, channel_id: &str) -> Result<String, WarError> {
+    match store.get(channel_id).await {
+        Err(WarError::NotFound) => {}
+        Ok(_) => {
+            return Err(WarError::Forbidden(
+                "this channel already has a war, ask an admin to claim it".to_string(),
+            ))
+        }
+        Err(e) => return Err(e),
+    }
+    let token = auth::generate()?;
+    if !store.set_token(channel_id, &token, false).await? {
+        return Err(WarError::Conflict(
+            "the channel was claimed concurrently".to_string(),
+        ));
+    }
+    Ok(token)
+}
+
+/// Pass `written` on, first releasing the token just claimed for it if the
+/// write failed, so the channel isn't left claimed by a token nobody got.
+async fn release_on_error(
+    store: &dyn WarStore,
+    channel_id: &str,
+    claimed: Option<&str>,
+    written: Result<WarData, WarError>,
+) -> Result<WarData, WarError> {
+    if let (Err(_), Some(token)) = (&written, claimed) {
+        if let Err(e) = store.release_token(channel_id, token).await {
+            warn!(target: channel_id, "cannot release unused token: {e}");
+        }
+    }
+    written
+}
+
+/// Issue a new channel token, invalidating the old one. Also claims
+/// unclaimed channels under the same rules as creating a war.
+#[post("/api/{channel_id}/token")]
+async fn rotate_token(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+
+    let token = match access {
+        Access::Unclaimed => claim(store.as_ref(), &channel_id).await?,
+        Access::Admin | Access::Owner => {
+            let token = auth::generate()?;
+            store.set_token(&channel_id, &token, true).await?;
+            token
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(NewToken { token }))
+}
+
+#[post("/api/{channel_id}/races")]
+async fn add_race(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    body: web::Json<RaceInput>,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+
+    apply(store.as_ref(), &channel_id, "add race", &|war| {
+        let mut war = existing(war)?;
+        if war.phase() == Phase::Final {
+            return Err(WarError::Conflict("the war is over".to_string()));
+        }
+        let scored = body.result.score(&war)?;
+        war.push_race(scored.home, scored.enemy, body.track);
+        if let Some(players) = &scored.players {
+            war.set_player_points(war.race_count() - 1, players);
+        }
+        Ok(war)
+    })
+    .await
+}
+
+#[put("/api/{channel_id}/races/{race}")]
+async fn edit_race(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, usize)>,
+    access: Access,
+    body: web::Json<RaceInput>,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let (channel_id, race) = path.into_inner();
+
+    apply(
+        store.as_ref(),
+        &channel_id,
+        &format!("edit race {race}"),
+        &|war| {
+            let mut war = existing(war)?;
+            let index = race_index(&war, race)?;
+            let scored = body.result.score(&war)?;
+            war.set_race(index, scored.home, scored.enemy);
+            if let Some(players) = &scored.players {
+                war.set_player_points(index, players);
+            }
+            if let (Some(track), Some(slot)) = (body.track, war.tracks.get_mut(index)) {
+                *slot = Some(track);
+            }
+            Ok(war)
+        },
+    )
+    .await
+}
+
+#[delete("/api/{channel_id}/races/{race}")]
+async fn delete_race(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, usize)>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let (channel_id, race) = path.into_inner();
+
+    apply(
+        store.as_ref(),
+        &channel_id,
+        &format!("delete race {race}"),
+        &|war| {
+            let mut war = existing(war)?;
+            let index = race_index(&war, race)?;
+            war.remove_race(index);
+            Ok(war)
+        },
+    )
+    .await
+}
+
+/// Replace the roster. Players who stay keep their points.
+#[put("/api/{channel_id}/roster")]
+async fn set_roster(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    body: web::Json<Roster>,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+    body.check()?;
+
+    apply(store.as_ref(), &channel_id, "set roster", &|war| {
+        let mut war = existing(war)?;
+        war.set_roster(&body.home, &body.enemy);
+        Ok(war)
+    })
+    .await
+}
+
+#[put("/api/{channel_id}/penalties")]
+async fn set_penalties(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    body: web::Json<SetPenalties>,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+    for total in [body.home_pen, body.enemy_pen].into_iter().flatten() {
+        check_penalty(total)?;
+    }
+
+    apply(store.as_ref(), &channel_id, "set penalties", &|war| {
+        let mut war = existing(war)?;
+        let race = war.race_count() as u32;
+        for (team, total) in [(Team::Home, body.home_pen), (Team::Enemy, body.enemy_pen)] {
+            let Some(total) = total else { continue };
+            let amount = total.checked_sub(war.penalty(team)).ok_or_else(|| {
+                WarError::BadRequest("penalty adjustment is out of range".to_string())
+            })?;
+            if amount != 0 {
+                let reason = Some(ADJUSTMENT.to_string());
+                war.penalties.push(Penalty::now(team, amount, race, reason));
+            }
+        }
+        Ok(war)
+    })
+    .await
+}
+
+#[post("/api/{channel_id}/penalties")]
+async fn add_penalty(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+    body: web::Json<AddPenalty>,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+    let reason = match body.reason.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(reason) if reason.len() > MAX_REASON_LEN => {
+            return Err(WarError::BadRequest(format!(
+                "reason must be at most {MAX_REASON_LEN} characters"
+            ))
+            .into())
+        }
+        Some(reason) => Some(reason.to_owned()),
+    };
+    check_penalty(body.amount)?;
+
+    apply(store.as_ref(), &channel_id, "add penalty", &|war| {
+        let mut war = existing(war)?;
+        let races = war.race_count() as u32;
+        let race = match body.race {
+            None => races,
+            Some(race) if (1..=races).contains(&race) => race,
+            Some(race) => {
+                return Err(WarError::BadRequest(format!(
+                    "race {race} does not exist, the war has {races} races"
+                )))
+            }
+        };
+        war.penalties
+            .push(Penalty::now(body.team, body.amount, race, reason.clone()));
+        Ok(war)
+    })
+    .await
+}
+
+fn check_penalty(amount: i32) -> Result<(), WarError> {
+    if !(-MAX_PENALTY..=MAX_PENALTY).contains(&amount) {
+        return Err(WarError::BadRequest(format!(
+            "penalties must be between -{MAX_PENALTY} and {MAX_PENALTY}"
+        )));
+    }
+    Ok(())
+}
+
+/// Drop one penalty (1-based, oldest first), shifting later ones down.
+#[delete("/api/{channel_id}/penalties/{penalty}")]
+async fn remove_penalty(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, usize)>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let (channel_id, penalty) = path.into_inner();
+
+    apply(
+        store.as_ref(),
+        &channel_id,
+        &format!("remove penalty {penalty}"),
+        &|war| {
+            let mut war = existing(war)?;
+            if penalty == 0 || penalty > war.penalties.len() {
+                return Err(WarError::BadRequest(format!(
+                    "penalty {penalty} does not exist, the war has {} penalties",
+                    war.penalties.len()
+                )));
+            }
+            war.penalties.remove(penalty - 1);
+            Ok(war)
+        },
+    )
+    .await
+}
+
+#[post("/api/{channel_id}/finish")]
+async fn finish_war(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+
+    apply(store.as_ref(), &channel_id, "finish war", &|war| {
+        let mut war = existing(war)?;
+        war.finished = true;
+        Ok(war)
+    })
+    .await
+}
+
+#[get("/api/{channel_id}/revisions")]
+async fn list_revisions(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+    let history = store.history(&channel_id).await?;
+
+    let revisions: Vec<RevisionSummary> = history
+        .revisions
+        .into_iter()
+        .enumerate()
+        .map(|(i, revision)| {
+            let races = revision.war.race_count();
+            let data = OverlayData::from(revision.war);
+            RevisionSummary {
+                revision: i + 1,
+                at: revision.at,
+                action: revision.action,
+                races,
+                score: data.score,
+                enemy_score: data.enemy_score,
+                live: i == history.cursor,
+            }
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+#[post("/api/{channel_id}/undo")]
+async fn undo(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+    let war = store.step(&channel_id, Step::Undo).await?;
+    written(store.as_ref(), &channel_id, war).await
+}
+
+#[post("/api/{channel_id}/redo")]
+async fn redo(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let channel_id = path.into_inner();
+    let war = store.step(&channel_id, Step::Redo).await?;
+    written(store.as_ref(), &channel_id, war).await
+}
+
+/// Make an earlier revision live again, as a new revision so it can be
+/// undone in turn.
+#[post("/api/{channel_id}/revisions/{revision}/restore")]
+async fn restore_revision(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, usize)>,
+    access: Access,
+) -> Result<impl Responder> {
+    access.require_owner()?;
+    let (channel_id, revision) = path.into_inner();
+    let history = store.history(&channel_id).await?;
+    let count = history.revisions.len();
+    let Some(restored) = revision
+        .checked_sub(1)
+        .and_then(|i| history.revisions.into_iter().nth(i))
+    else {
+        return Err(WarError::BadRequest(format!(
+            "revision {revision} does not exist, the war has {count} revisions"
+        ))
+        .into());
+    };
+
+    let action = format!("restore revision {revision}");
+    apply(store.as_ref(), &channel_id, &action, &|_| {
+        Ok(restored.war.clone())
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::format::WarFormat;
+    use crate::memory_store::MemoryStore;
+    use crate::testing::{war, war_in};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const TOKEN: &str = "secret";
+
+    macro_rules! app {
+        ($store:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::from($store.clone()))
+                    .app_data(web::Data::new(Config::default()))
+                    .service(create_war)
+                    .service(add_race)
+                    .service(edit_race)
+                    .service(delete_race),
+            )
+            .await
+        };
+    }
+
+    /// A store holding `war` on channel `ch`, claimed with [`TOKEN`].
+    async fn owned(war: WarData) -> Arc<dyn WarStore> {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        store.put("ch", &war).await.unwrap();
+        store.set_token("ch", TOKEN, true).await.unwrap();
+        store
+    }
+
+    fn owner(req: TestRequest) -> TestRequest {
+        req.insert_header(("authorization", format!("Bearer {TOKEN}")))
+    }
+
+    #[test]
+    fn positions_may_be_written_out() {
+        let result = RaceResult::Positions {
+            positions: Positions::Text("1 2,3  4 5 6".to_string()),
+        };
+        let scored = result.score(&war(0)).unwrap();
+        assert_eq!((scored.home, scored.enemy), (61.0, 21.0));
+
+        let result = RaceResult::Positions {
+            positions: Positions::Text("1 2 x".to_string()),
+        };
+        assert!(result.score(&war(0)).is_err());
+    }
+
+    /// A 2v2 war with no races, A and B against X and Y.
+    fn pairs() -> WarData {
+        let format = WarFormat {
+            team_size: 2,
+            points: vec![15, 12, 10, 9],
+            ..WarFormat::default()
+        };
+        let mut war = war_in(format, 0);
+        war.set_roster(&["A".into(), "B".into()], &["X".into(), "Y".into()]);
+        war
+    }
+
+    fn positions(entries: &[(&str, u32)]) -> BTreeMap<String, u32> {
+        entries
+            .iter()
+            .map(|&(name, position)| (name.to_string(), position))
+            .collect()
+    }
+
+    #[test]
+    fn players_score_their_positions() {
+        let scored = score_players(&pairs(), &positions(&[("A", 1), ("B", 4), ("X", 2)])).unwrap();
+        assert_eq!((scored.home, scored.enemy), (24.0, 22.0));
+        assert_eq!(
+            scored.players,
+            Some(vec![Some(15), Some(9), Some(12), None])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_player_positions() {
+        let war = pairs();
+        for entries in [
+            &[("A", 1), ("C", 2)][..],
+            &[("A", 1), ("B", 1)],
+            &[("A", 1), ("B", 5)],
+            &[("A", 1), ("X", 2)],
+        ] {
+            let res = score_players(&war, &positions(entries));
+            assert!(matches!(res, Err(WarError::BadRequest(_))), "{entries:?}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn editing_totals_keeps_player_points() {
+        let store = owned(pairs()).await;
+        let app = app!(store);
+        let req = owner(TestRequest::post().uri("/api/ch/races"))
+            .set_json(json!({"players": {"A": 1, "B": 4, "X": 2}}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = owner(TestRequest::put().uri("/api/ch/races/1"))
+            .set_json(json!({"home_score": 25, "enemy_score": 21}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let war = store.get("ch").await.unwrap();
+        assert_eq!(war.home_score, [25.0]);
+        assert_eq!(war.roster[0].points, [Some(15)]);
+
+        let req = owner(TestRequest::put().uri("/api/ch/races/1"))
+            .set_json(json!({"players": {"A": 2, "B": 3}}))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let war = store.get("ch").await.unwrap();
+        assert_eq!(war.roster[0].points, [Some(12)]);
+        assert_eq!(war.roster[2].points, [None]);
+    }
+
+    #[actix_web::test]
+    async fn creating_claims_the_channel() {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        let app = app!(store);
+        let new_war = json!({"tag": "ABC", "enemy_tag": "XYZ", "format": {"races": 1}});
+
+        let req = TestRequest::post()
+            .uri("/api/ch")
+            .set_json(&new_war)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let created: Value = test::read_body_json(res).await;
+        let token = created["token"].as_str().unwrap().to_owned();
+        assert_eq!(store.token("ch").await.unwrap(), Some(token.clone()));
+
+        let race = json!({"home_score": 50, "enemy_score": 32});
+        let req = TestRequest::post()
+            .uri("/api/ch/races")
+            .set_json(&race)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let req = TestRequest::post()
+            .uri("/api/ch/races")
+            .insert_header(("authorization", "Bearer wrong"))
+            .set_json(&race)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = TestRequest::post()
+            .uri("/api/ch/races")
+            .insert_header(("authorization", format!("Bearer {token}")))
+            .set_json(&race)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // The next war on the channel needs the token too.
+        let req = TestRequest::post()
+            .uri("/api/ch")
+            .set_json(&new_war)
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn failed_writes_release_their_claim() {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        store.set_token("ch", "claimed", false).await.unwrap();
+        let failed = Err(WarError::Unavailable("down".to_string()));
+        let res = release_on_error(store.as_ref(), "ch", Some("claimed"), failed).await;
+        assert!(res.is_err());
+        assert_eq!(store.token("ch").await.unwrap(), None);
+
+        // Someone else's token stays.
+        store.set_token("ch", "theirs", false).await.unwrap();
+        let failed = Err(WarError::Unavailable("down".to_string()));
+        let _ = release_on_error(store.as_ref(), "ch", Some("claimed"), failed).await;
+        assert_eq!(store.token("ch").await.unwrap().as_deref(), Some("theirs"));
+    }
+
+    #[actix_web::test]
+    async fn races_are_added_edited_and_deleted() {
+        let store = owned(war(1)).await;
+        let app = app!(store);
+
+        let req = owner(TestRequest::post().uri("/api/ch/races"))
+            .set_json(json!({"home_score": 40, "enemy_score": 42}))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            (&body["score"], &body["enemy_score"]),
+            (&json!(90), &json!(74))
+        );
+
+        let req = owner(TestRequest::put().uri("/api/ch/races/2"))
+            .set_json(json!({"home_score": 41, "enemy_score": 41}))
+            .to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["last_diff"], 0);
+
+        let req = owner(TestRequest::delete().uri("/api/ch/races/1")).to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["score"], 41);
+        assert_eq!(store.get("ch").await.unwrap().race_count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn rejects_races_that_do_not_exist() {
+        let store = owned(war(1)).await;
+        let app = app!(store);
+        for race in [0, 2] {
+            let req = owner(TestRequest::put().uri(&format!("/api/ch/races/{race}")))
+                .set_json(json!({"home_score": 41, "enemy_score": 41}))
+                .to_request();
+            let res = test::call_service(&app, req).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_web::test]
+    async fn half_written_wars_are_not_edited_by_race() {
+        let mut war = war(1);
+        war.diff.push(0);
+        let store = owned(war).await;
+        let app = app!(store);
+        let req = owner(TestRequest::delete().uri("/api/ch/races/2")).to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,417 @@
+use crate::error::WarError;
+use crate::history::History;
+use crate::migrate;
+use crate::store::WarStore;
+use crate::validate::{self, Policy};
+use crate::war::{unix_now, OverlayData, Phase, WarData};
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Archived wars listed per page unless asked otherwise.
+const DEFAULT_PER_PAGE: usize = 20;
+const MAX_PER_PAGE: usize = 100;
+
+/// A finished war as kept in a channel's archive.
+#[derive(Clone)]
+pub struct ArchivedWar {
+    /// Start time, tags and a hash telling wars apart, e.g.
+    /// `20261018T201500-ABC-XYZ-5c1e0a3b9f2d`. A war started through the API
+    /// keeps its id when archived again; the hash covers the channel and the
+    /// full tags. Wars without a start time use the time they were archived
+    /// instead and hash the whole war too.
+    pub id: String,
+    pub channel_id: String,
+    /// Unix seconds of the last time it was archived.
+    pub finished_at: Option<u64>,
+    /// Unix seconds each race was entered, where the channel's history
+    /// still knew.
+    pub race_times: Vec<Option<u64>>,
+    pub war: WarData,
+}
+
+#[derive(Serialize)]
+struct Encoded<'a> {
+    id: &'a str,
+    channel_id: &'a str,
+    finished_at: Option<u64>,
+    race_times: &'a [Option<u64>],
+    war: Value,
+}
+
+#[derive(Deserialize)]
+struct Decoded {
+    id: String,
+    channel_id: String,
+    finished_at: Option<u64>,
+    #[serde(default)]
+    race_times: Vec<Option<u64>>,
+    war: Value,
+}
+
+impl ArchivedWar {
+    /// `war` as archived now, if it is over. `revisions` is the channel's
+    /// history, for when each race was entered.
+    pub fn of(channel_id: &str, war: &WarData, revisions: &History) -> Option<Self> {
+        if war.phase() != Phase::Final {
+            return None;
+        }
+        let finished_at = unix_now();
+        let (stamp, hash) = match war.started_at {
+            Some(started) => (started, fnv1a(&[channel_id, &war.tag, &war.enemy_tag])),
+            None => (
+                finished_at.unwrap_or(0),
+                fnv1a(&[channel_id, &war.tag, &war.enemy_tag, &migrate::encode(war)]),
+            ),
+        };
+        Some(Self {
+            id: format!(
+                "{}-{}-{}-{:012x}",
+                utc_stamp(stamp),
+                slug(&war.tag),
+                slug(&war.enemy_tag),
+                hash >> 16
+            ),
+            channel_id: channel_id.to_owned(),
+            finished_at,
+            race_times: race_times(war, revisions),
+            war: war.clone(),
+        })
+    }
+
+    /// Serialize with the war in the current stored format, so archived
+    /// wars are upgraded on read like live ones.
+    pub fn encode(&self) -> String {
+        serde_json::to_string(&Encoded {
+            id: &self.id,
+            channel_id: &self.channel_id,
+            finished_at: self.finished_at,
+            race_times: &self.race_times,
+            war: migrate::to_value(&self.war),
+        })
+        .unwrap()
+    }
+
+    pub fn decode(json: &str) -> Result<Self, WarError> {
+        let decoded: Decoded = serde_json::from_str(json).map_err(WarError::from_json)?;
+        Ok(Self {
+            id: decoded.id,
+            channel_id: decoded.channel_id,
+            finished_at: decoded.finished_at,
+            race_times: decoded.race_times,
+            war: migrate::upgrade(decoded.war)?.war,
+        })
+    }
+
+    fn summary(&self) -> Summary {
+        let (score, enemy_score) = self.war.totals();
+        Summary {
+            id: self.id.clone(),
+            started_at: self.war.started_at,
+            finished_at: self.finished_at,
+            tag: self.war.tag.clone(),
+            enemy_tag: self.war.enemy_tag.clone(),
+            score,
+            enemy_score,
+            races: self.war.race_count(),
+        }
+    }
+}
+
+/// Archive `war` if it is over and not the channel's latest archived war
+/// already, replacing an earlier snapshot of the same war. Called on writes
+/// and reads alike, so wars the bot finishes are kept too. Failures are
+/// logged: the live war is what matters.
+pub async fn keep(store: &dyn WarStore, channel_id: &str, war: &WarData) {
+    if war.phase() != Phase::Final {
+        return;
+    }
+    match store.archived(channel_id, 0, 1).await {
+        Ok((latest, _)) if latest.first().is_some_and(|kept| kept.war == *war) => return,
+        Ok(_) => {}
+        Err(e) => {
+            warn!(target: channel_id, "cannot check the archive: {e}");
+            return;
+        }
+    }
+    let revisions = store.history(channel_id).await.unwrap_or_else(|e| {
+        warn!(target: channel_id, "archiving without race times: {e}");
+        History::default()
+    });
+    let Some(archived) = ArchivedWar::of(channel_id, war, &revisions) else {
+        return;
+    };
+    if let Err(e) = store.archive(&archived).await {
+        warn!(target: channel_id, "cannot archive {}: {e}", archived.id);
+    }
+}
+
+/// When each race of `war` was entered, from the revisions of the same war
+/// that added races. Wars without a start time can't be told apart from
+/// the ones before them.
+fn race_times(war: &WarData, revisions: &History) -> Vec<Option<u64>> {
+    let mut times = vec![None; war.race_count()];
+    if war.started_at.is_none() {
+        return times;
+    }
+    let live = revisions.revisions.iter().take(revisions.cursor + 1);
+    let mut races = 0;
+    for revision in live.filter(|revision| revision.war.started_at == war.started_at) {
+        let count = revision.war.race_count().min(times.len());
+        for time in times.iter_mut().take(count).skip(races) {
+            *time = revision.at;
+        }
+        races = revision.war.race_count();
+    }
+    times
+}
+
+/// Tags as they may appear in an id.
+fn slug(tag: &str) -> String {
+    tag.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// 64-bit FNV-1a of `parts`, kept by hand since ids outlive builds and the
+/// standard hasher may change between them.
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        // 0xff never occurs in UTF-8, so parts can't run into each other.
+        for &byte in part.as_bytes().iter().chain(&[0xff]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// `YYYYMMDDTHHMMSS` in UTC.
+fn utc_stamp(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+    // Days to civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}",
+        secs / 3_600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// An archived war as listed.
+#[derive(Serialize)]
+struct Summary {
+    id: String,
+    started_at: Option<u64>,
+    finished_at: Option<u64>,
+    tag: String,
+    enemy_tag: String,
+    score: i32,
+    enemy_score: i32,
+    races: usize,
+}
+
+#[derive(Serialize)]
+struct Page {
+    page: usize,
+    per_page: usize,
+    total: usize,
+    wars: Vec<Summary>,
+}
+
+/// An archived war in full.
+#[derive(Serialize)]
+struct Detail {
+    id: String,
+    channel_id: String,
+    started_at: Option<u64>,
+    finished_at: Option<u64>,
+    #[serde(flatten)]
+    war: OverlayData,
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    /// 1-based.
+    page: Option<usize>,
+    per_page: Option<usize>,
+}
+
+/// The channel's finished wars, newest first.
+#[get("/api/{channel_id}/history")]
+async fn history(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    if page == 0 || per_page == 0 || per_page > MAX_PER_PAGE {
+        return Err(WarError::BadRequest(format!(
+            "page starts at 1 and per_page must be between 1 and {MAX_PER_PAGE}"
+        ))
+        .into());
+    }
+
+    let offset = (page - 1)
+        .checked_mul(per_page)
+        .ok_or_else(|| WarError::BadRequest(format!("page {page} is out of range")))?;
+    let (wars, total) = store.archived(&channel_id, offset, per_page).await?;
+    Ok(HttpResponse::Ok().json(Page {
+        page,
+        per_page,
+        total,
+        wars: wars.iter().map(ArchivedWar::summary).collect(),
+    }))
+}
+
+#[get("/api/{channel_id}/history/{war_id}")]
+async fn archived_war(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let (channel_id, war_id) = path.into_inner();
+    let archived = store.archived_war(&channel_id, &war_id).await?;
+    Ok(HttpResponse::Ok().json(Detail {
+        id: archived.id,
+        channel_id: archived.channel_id,
+        started_at: archived.war.started_at,
+        finished_at: archived.finished_at,
+        war: validate::overlay(archived.war, Policy::Warn)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::Revision;
+    use crate::memory_store::MemoryStore;
+    use crate::testing::war;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use std::sync::Arc;
+
+    /// [`war`], started at a fixed time.
+    fn started(races: usize) -> WarData {
+        let mut war = war(races);
+        war.started_at = Some(1_000);
+        war
+    }
+
+    fn id(channel_id: &str, war: &WarData) -> String {
+        ArchivedWar::of(channel_id, war, &History::default())
+            .unwrap()
+            .id
+    }
+
+    #[test]
+    fn stamps_are_utc() {
+        assert_eq!(utc_stamp(0), "19700101T000000");
+        assert_eq!(utc_stamp(1_792_354_500), "20261018T201500");
+        assert_eq!(utc_stamp(951_868_799), "20000229T235959");
+    }
+
+    #[test]
+    fn only_finished_wars_are_archived() {
+        assert!(ArchivedWar::of("ch", &started(11), &History::default()).is_none());
+        assert!(id("ch", &started(12)).starts_with("19700101T001640-ABC-XYZ-"));
+    }
+
+    #[test]
+    fn ids_tell_wars_apart() {
+        let war = started(12);
+        assert_eq!(id("ch", &war), id("ch", &war));
+        assert_ne!(id("ch", &war), id("other", &war));
+
+        let mut omega = war.clone();
+        omega.tag = "ΩΩ".to_string();
+        let mut delta = war.clone();
+        delta.tag = "ΔΔ".to_string();
+        assert_ne!(id("ch", &omega), id("ch", &delta));
+
+        let mut bot = war.clone();
+        bot.started_at = None;
+        let mut other = bot.clone();
+        other.set_race(0, 41.0, 41.0);
+        assert_ne!(id("ch", &bot), id("ch", &other));
+    }
+
+    #[test]
+    fn races_are_timed_from_the_revisions_of_the_same_war() {
+        let at = |at, war| Revision {
+            at: Some(at),
+            action: "add race".to_string(),
+            war,
+        };
+        let mut earlier = war(1);
+        earlier.started_at = Some(5);
+        let revisions = History {
+            revisions: vec![
+                at(50, earlier),
+                at(100, started(0)),
+                at(110, started(1)),
+                at(130, started(3)),
+                at(140, started(4)),
+            ],
+            cursor: 3,
+        };
+        assert_eq!(
+            race_times(&started(4), &revisions),
+            [Some(110), Some(130), Some(130), None]
+        );
+
+        let mut bot = started(1);
+        bot.started_at = None;
+        assert_eq!(race_times(&bot, &revisions), [None]);
+    }
+
+    #[actix_web::test]
+    async fn keeps_each_finished_war_once() {
+        let store = MemoryStore::default();
+        keep(&store, "ch", &started(11)).await;
+        keep(&store, "ch", &started(12)).await;
+        keep(&store, "ch", &started(12)).await;
+        assert_eq!(store.archived("ch", 0, 10).await.unwrap().1, 1);
+
+        let mut bot = started(12);
+        bot.started_at = None;
+        keep(&store, "ch", &bot).await;
+        keep(&store, "ch", &bot).await;
+        assert_eq!(store.archived("ch", 0, 10).await.unwrap().1, 2);
+
+        bot.set_race(0, 41.0, 41.0);
+        keep(&store, "ch", &bot).await;
+        assert_eq!(store.archived("ch", 0, 10).await.unwrap().1, 3);
+    }
+
+    #[actix_web::test]
+    async fn pages_past_the_end_are_rejected() {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        let app =
+            test::init_service(App::new().app_data(web::Data::from(store)).service(history)).await;
+        let req = TestRequest::get()
+            .uri(&format!("/api/ch/history?page={}&per_page=100", usize::MAX))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+        let req = TestRequest::get()
+            .uri("/api/ch/history?page=2")
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::Config;
+use crate::error::WarError;
+use crate::store::WarStore;
+use actix_web::dev::Payload;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::{web, FromRequest, HttpRequest};
+use futures_util::future::LocalBoxFuture;
+use std::fmt::Write;
+
+/// Random bytes per generated token.
+const TOKEN_BYTES: usize = 24;
+
+/// Who is making a mutating request, from its `Authorization: Bearer` header.
+///
+/// Extracting this never logs or echoes the token.
+pub enum Access {
+    /// Presented the global admin token.
+    Admin,
+    /// Presented the channel's own token.
+    Owner,
+    /// The channel has no token yet and no admin token was presented.
+    Unclaimed,
+}
+
+impl Access {
+    /// Reject callers that don't hold the channel or admin token.
+    pub fn require_owner(&self) -> Result<(), WarError> {
+        match self {
+            Access::Admin | Access::Owner => Ok(()),
+            Access::Unclaimed => Err(WarError::Forbidden(
+                "this channel has no token, create a war first or ask an admin".to_string(),
+            )),
+        }
+    }
+}
+
+impl FromRequest for Access {
+    type Error = WarError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let store = req.app_data::<web::Data<dyn WarStore>>().cloned();
+        let config = req.app_data::<web::Data<Config>>().cloned();
+        let channel_id = req.match_info().get("channel_id").map(str::to_owned);
+        let presented = bearer(req);
+
+        Box::pin(async move {
+            let (Some(store), Some(config), Some(channel_id)) = (store, config, channel_id) else {
+                return Err(WarError::Unavailable(
+                    "authorization is not configured".to_string(),
+                ));
+            };
+
+            if let (Some(admin), Some(presented)) = (&config.admin_token, &presented) {
+                if same(admin, presented) {
+                    return Ok(Access::Admin);
+                }
+            }
+            match (store.token(&channel_id).await?, presented) {
+                (None, _) => Ok(Access::Unclaimed),
+                (Some(_), None) => Err(WarError::Unauthorized),
+                (Some(token), Some(presented)) if same(&token, &presented) => Ok(Access::Owner),
+                (Some(_), Some(_)) => Err(WarError::Forbidden("wrong token".to_string())),
+            }
+        })
+    }
+}
+
+fn bearer(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let token = value.strip_prefix("Bearer ")?.trim();
+    (!token.is_empty()).then(|| token.to_owned())
+}
+
+/// Compare without bailing out at the first differing byte.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// A fresh random channel token.
+pub fn generate() -> Result<String, WarError> {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    getrandom::fill(&mut bytes).map_err(|e| WarError::Unavailable(e.to_string()))?;
+    let mut token = String::with_capacity(TOKEN_BYTES * 2);
+    for b in bytes {
+        let _ = write!(token, "{b:02x}");
+    }
+    Ok(token)
+}
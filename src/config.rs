@@ -0,0 +1,278 @@
+use crate::validate::Policy;
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Environment variable pointing at an optional TOML config file.
+const CONFIG_PATH_VAR: &str = "WAR_SCORE_CONFIG";
+
+const MIN_ADMIN_TOKEN_LEN: usize = 16;
+
+/// How websocket sessions learn that a war changed.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Notifications {
+    /// Redis keyspace notifications on the war key (`notify-keyspace-events`
+    /// must include `K` and `$` or `A`).
+    Keyspace,
+    /// A pub/sub channel named `update_channel_prefix` + channel id that the
+    /// bot publishes to after every write.
+    Pubsub,
+    /// Re-read the war every `poll_interval_ms`.
+    Poll,
+}
+
+impl FromStr for Notifications {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keyspace" => Ok(Notifications::Keyspace),
+            "pubsub" => Ok(Notifications::Pubsub),
+            "poll" => Ok(Notifications::Poll),
+            _ => Err("expected one of keyspace, pubsub, poll".to_string()),
+        }
+    }
+}
+
+/// Which [`WarStore`](crate::store::WarStore) backend to run with.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Redis,
+    /// Process memory only; everything is lost on restart.
+    Memory,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redis" => Ok(Backend::Redis),
+            "memory" => Ok(Backend::Memory),
+            _ => Err("expected one of redis, memory".to_string()),
+        }
+    }
+}
+
+/// Runtime settings.
+///
+/// Values are layered: built-in defaults, then the TOML file named by
+/// `WAR_SCORE_CONFIG` (if any), then individual `WAR_SCORE_*` variables.
+// No `Debug`: it would print `admin_token`.
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub store: Backend,
+    /// JSON object of channel id to war, written to the store at startup.
+    pub seed_file: Option<PathBuf>,
+    pub redis_url: String,
+    pub bind: SocketAddr,
+    pub poll_interval_ms: u64,
+    pub heartbeat_interval_secs: u64,
+    pub client_timeout_secs: u64,
+    pub notifications: Notifications,
+    pub update_channel_prefix: String,
+    /// Write wars stored in an older schema version back in the current one
+    /// when they are read.
+    pub rewrite_migrated: bool,
+    /// How to serve wars whose scores and diffs don't add up.
+    pub validation: Policy,
+    /// Grants write access to every channel.
+    pub admin_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            store: Backend::Redis,
+            seed_file: None,
+            redis_url: "redis://redis:6379".to_string(),
+            bind: SocketAddr::from(([0, 0, 0, 0], 25991)),
+            poll_interval_ms: 1000,
+            heartbeat_interval_secs: 30,
+            client_timeout_secs: 75,
+            notifications: Notifications::Keyspace,
+            update_channel_prefix: "war_score:".to_string(),
+            rewrite_migrated: false,
+            validation: Policy::Warn,
+            admin_token: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env {
+        var: &'static str,
+        value: String,
+        reason: String,
+    },
+    Invalid {
+        field: &'static str,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {e}", path.display()),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse {}: {e}", path.display()),
+            ConfigError::Env { var, value, reason } => {
+                write!(f, "{var}={value:?} is invalid: {reason}")
+            }
+            ConfigError::Invalid { field, reason } => write!(f, "{field}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Build the config from the process environment and validate it.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var_os(CONFIG_PATH_VAR) {
+            Some(path) => Self::from_file(PathBuf::from(path))?,
+            None => Self::default(),
+        };
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: PathBuf) -> Result<Self, ConfigError> {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(v) => v,
+            Err(e) => return Err(ConfigError::Read(path, e)),
+        };
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path, e))
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Some(v) = env_var("WAR_SCORE_STORE")? {
+            self.store = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_SEED_FILE")? {
+            self.seed_file = Some(v);
+        }
+        if let Some(v) = env_var("WAR_SCORE_REDIS_URL")? {
+            self.redis_url = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_BIND")? {
+            self.bind = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_POLL_INTERVAL_MS")? {
+            self.poll_interval_ms = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_HEARTBEAT_INTERVAL_SECS")? {
+            self.heartbeat_interval_secs = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_CLIENT_TIMEOUT_SECS")? {
+            self.client_timeout_secs = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_NOTIFICATIONS")? {
+            self.notifications = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_UPDATE_CHANNEL_PREFIX")? {
+            self.update_channel_prefix = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_REWRITE_MIGRATED")? {
+            self.rewrite_migrated = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_VALIDATION")? {
+            self.validation = v;
+        }
+        if let Some(v) = env_var("WAR_SCORE_ADMIN_TOKEN")? {
+            self.admin_token = Some(v);
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        // The memory store never connects, so any URL will do.
+        if self.store == Backend::Redis {
+            if let Err(e) = redis::Client::open(self.redis_url.as_str()) {
+                return Err(ConfigError::Invalid {
+                    field: "redis_url",
+                    reason: e.to_string(),
+                });
+            }
+        }
+        if self.poll_interval_ms == 0 {
+            return Err(ConfigError::Invalid {
+                field: "poll_interval_ms",
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        if self.heartbeat_interval_secs == 0 {
+            return Err(ConfigError::Invalid {
+                field: "heartbeat_interval_secs",
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        if self.client_timeout_secs <= self.heartbeat_interval_secs {
+            return Err(ConfigError::Invalid {
+                field: "client_timeout_secs",
+                reason: format!(
+                    "must be greater than heartbeat_interval_secs ({})",
+                    self.heartbeat_interval_secs
+                ),
+            });
+        }
+        if let Some(token) = &self.admin_token {
+            if token.len() < MIN_ADMIN_TOKEN_LEN {
+                return Err(ConfigError::Invalid {
+                    field: "admin_token",
+                    reason: format!("must be at least {MIN_ADMIN_TOKEN_LEN} characters"),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms)
+    }
+
+    pub fn heartbeat_interval(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_interval_secs)
+    }
+
+    pub fn client_timeout(&self) -> Duration {
+        Duration::from_secs(self.client_timeout_secs)
+    }
+}
+
+/// Read and parse `var`, treating an unset variable as "no override".
+fn env_var<T>(var: &'static str) -> Result<Option<T>, ConfigError>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let value = match std::env::var(var) {
+        Ok(v) => v,
+        Err(std::env::VarError::NotPresent) => return Ok(None),
+        Err(e) => {
+            return Err(ConfigError::Env {
+                var,
+                value: String::new(),
+                reason: e.to_string(),
+            })
+        }
+    };
+    match value.parse() {
+        Ok(v) => Ok(Some(v)),
+        Err(e) => Err(ConfigError::Env {
+            var,
+            reason: e.to_string(),
+            value,
+        }),
+    }
+}
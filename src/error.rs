@@ -0,0 +1,100 @@
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use std::fmt;
+
+/// Why a war could not be served or changed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WarError {
+    /// Nothing stored for this channel.
+    NotFound,
+    /// The store could not be reached.
+    Unavailable(String),
+    /// The stored value is not valid JSON.
+    Malformed(String),
+    /// The stored value is JSON but not shaped like a war.
+    Schema(String),
+    /// The war is shaped right but its numbers don't add up.
+    Invalid(String),
+    /// The request asked for something impossible.
+    BadRequest(String),
+    /// The request clashes with the war's current state.
+    Conflict(String),
+    /// A mutating request came without a token.
+    Unauthorized,
+    /// A mutating request came with a token that doesn't grant access.
+    Forbidden(String),
+}
+
+impl WarError {
+    /// Stable identifier sent to clients alongside the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarError::NotFound => "no_war",
+            WarError::Unavailable(_) => "store_unavailable",
+            WarError::Malformed(_) => "malformed_data",
+            WarError::Schema(_) => "schema_mismatch",
+            WarError::Invalid(_) => "invalid_data",
+            WarError::BadRequest(_) => "bad_request",
+            WarError::Conflict(_) => "conflict",
+            WarError::Unauthorized => "unauthorized",
+            WarError::Forbidden(_) => "forbidden",
+        }
+    }
+
+    /// Split a deserialization failure into syntax and shape problems.
+    pub fn from_json(e: serde_json::Error) -> Self {
+        match e.classify() {
+            serde_json::error::Category::Data => WarError::Schema(e.to_string()),
+            _ => WarError::Malformed(e.to_string()),
+        }
+    }
+
+    /// JSON body shared by the API and the websocket.
+    pub fn body(&self) -> serde_json::Value {
+        json!({ "error": self.to_string(), "code": self.code() })
+    }
+}
+
+impl fmt::Display for WarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarError::NotFound => f.write_str("no war for this channel"),
+            WarError::Unavailable(e) => write!(f, "war store unavailable: {e}"),
+            WarError::Malformed(e) => write!(f, "stored war is not valid JSON: {e}"),
+            WarError::Schema(e) => write!(f, "stored war has an unexpected shape: {e}"),
+            WarError::Invalid(e) => write!(f, "stored war is inconsistent: {e}"),
+            WarError::BadRequest(e) | WarError::Conflict(e) | WarError::Forbidden(e) => {
+                f.write_str(e)
+            }
+            WarError::Unauthorized => f.write_str("missing bearer token"),
+        }
+    }
+}
+
+impl std::error::Error for WarError {}
+
+impl ResponseError for WarError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WarError::NotFound => StatusCode::NOT_FOUND,
+            WarError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            WarError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            WarError::Conflict(_) => StatusCode::CONFLICT,
+            WarError::Unauthorized => StatusCode::UNAUTHORIZED,
+            WarError::Forbidden(_) => StatusCode::FORBIDDEN,
+            WarError::Malformed(_) | WarError::Schema(_) | WarError::Invalid(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+        if *self == WarError::Unauthorized {
+            res.insert_header((WWW_AUTHENTICATE, "Bearer"));
+        }
+        res.json(self.body())
+    }
+}
@@ -0,0 +1,239 @@
+use crate::store::WarStore;
+use crate::track::Track;
+use crate::validate;
+use crate::war::{race_diff, Team, WarData};
+use actix_web::http::header::ContentDisposition;
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use serde::{Deserialize, Serialize};
+
+/// Column order of the CSV export. Spreadsheets import by position, so
+/// columns are only ever added at the end.
+const COLUMNS: [&str; 12] = [
+    "race",
+    "track",
+    "tag",
+    "enemy_tag",
+    "home_score",
+    "enemy_score",
+    "race_diff",
+    "home_penalty",
+    "enemy_penalty",
+    "home_total",
+    "enemy_total",
+    "total_diff",
+];
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// One race as exported. Field order matches [`COLUMNS`].
+#[derive(Serialize)]
+struct Row<'a> {
+    /// 1-based.
+    race: usize,
+    track: &'static str,
+    tag: &'a str,
+    enemy_tag: &'a str,
+    home_score: f64,
+    enemy_score: f64,
+    race_diff: i32,
+    /// Penalty points given after this race; ones given before the first
+    /// race count towards it, ones given after the last towards that.
+    home_penalty: i32,
+    enemy_penalty: i32,
+    /// Running totals after this race, penalties included.
+    home_total: i32,
+    enemy_total: i32,
+    total_diff: i32,
+}
+
+impl Row<'_> {
+    fn csv(&self, out: &mut String) {
+        let fields = [
+            self.race.to_string(),
+            self.track.to_string(),
+            csv_field(self.tag),
+            csv_field(self.enemy_tag),
+            self.home_score.to_string(),
+            self.enemy_score.to_string(),
+            self.race_diff.to_string(),
+            self.home_penalty.to_string(),
+            self.enemy_penalty.to_string(),
+            self.home_total.to_string(),
+            self.enemy_total.to_string(),
+            self.total_diff.to_string(),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+}
+
+/// One row per race, derived from the scores. The last row's totals match
+/// the war's.
+fn rows(war: &WarData) -> Vec<Row<'_>> {
+    let races = war.home_score.len().min(war.enemy_score.len());
+    let penalty = |team: Team, race: usize| -> i32 {
+        war.penalties
+            .iter()
+            .filter(|penalty| {
+                penalty.team == team && (penalty.race as usize).clamp(1, races.max(1)) == race
+            })
+            .map(|penalty| penalty.amount)
+            .sum()
+    };
+
+    let (mut home_sum, mut enemy_sum) = (0.0, 0.0);
+    let (mut home_pen, mut enemy_pen) = (0, 0);
+    war.home_score
+        .iter()
+        .zip(&war.enemy_score)
+        .enumerate()
+        .map(|(i, (&home_score, &enemy_score))| {
+            let race = i + 1;
+            let (home_penalty, enemy_penalty) =
+                (penalty(Team::Home, race), penalty(Team::Enemy, race));
+            home_sum += home_score;
+            enemy_sum += enemy_score;
+            home_pen += home_penalty;
+            enemy_pen += enemy_penalty;
+            let home_total = home_sum.round() as i32 - home_pen;
+            let enemy_total = enemy_sum.round() as i32 - enemy_pen;
+            Row {
+                race,
+                track: war.tracks.get(i).copied().flatten().map_or("", Track::abbr),
+                tag: &war.tag,
+                enemy_tag: &war.enemy_tag,
+                home_score,
+                enemy_score,
+                race_diff: race_diff(home_score, enemy_score),
+                home_penalty,
+                enemy_penalty,
+                home_total,
+                enemy_total,
+                total_diff: home_total - enemy_total,
+            }
+        })
+        .collect()
+}
+
+/// Quote a field if a spreadsheet would otherwise split or misread it.
+/// Text that would start a formula gets a leading `'`, so a tag like
+/// `=HYPERLINK(..)` stays text.
+fn csv_field(text: &str) -> String {
+    let text = if text.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{text}")
+    } else {
+        text.to_string()
+    };
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+/// `war` as a download named `name` plus the format's extension.
+fn export(mut war: WarData, name: &str, format: ExportFormat) -> HttpResponse {
+    validate::repair(&mut war);
+    let rows = rows(&war);
+    match format {
+        ExportFormat::Csv => {
+            let mut body = COLUMNS.join(",");
+            body.push_str("\r\n");
+            for row in &rows {
+                row.csv(&mut body);
+            }
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(ContentDisposition::attachment(format!("{name}.csv")))
+                .body(body)
+        }
+        ExportFormat::Json => HttpResponse::Ok()
+            .insert_header(ContentDisposition::attachment(format!("{name}.json")))
+            .json(rows),
+    }
+}
+
+/// The channel's current war, one row per race.
+#[get("/api/{channel_id}/export")]
+async fn live(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> Result<impl Responder> {
+    let channel_id = path.into_inner();
+    let war = store.get(&channel_id).await?;
+    Ok(export(war, &channel_id, query.format))
+}
+
+#[get("/api/{channel_id}/history/{war_id}/export")]
+async fn archived(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, String)>,
+    query: web::Query<ExportQuery>,
+) -> Result<impl Responder> {
+    let (channel_id, war_id) = path.into_inner();
+    let archived = store.archived_war(&channel_id, &war_id).await?;
+    Ok(export(archived.war, &archived.id, query.format))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::war::Penalty;
+
+    /// Two races, with a home penalty before the first and an enemy one
+    /// after the second.
+    fn war() -> WarData {
+        let mut war = testing::war(1);
+        war.push_race(40.0, 42.0, None);
+        war.penalties.push(Penalty::now(Team::Home, 10, 0, None));
+        war.penalties.push(Penalty::now(Team::Enemy, 5, 2, None));
+        war
+    }
+
+    #[test]
+    fn rows_keep_running_totals() {
+        let war = war();
+        let rows = rows(&war);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].race_diff, 18);
+        assert_eq!(rows[0].home_penalty, 10);
+        assert_eq!((rows[0].home_total, rows[0].enemy_total), (40, 32));
+        assert_eq!(rows[1].enemy_penalty, 5);
+        assert_eq!((rows[1].home_total, rows[1].enemy_total), (80, 69));
+        assert_eq!(rows[1].total_diff, 11);
+    }
+
+    #[test]
+    fn late_penalties_count_in_the_last_row() {
+        let mut war = war();
+        war.penalties.push(Penalty::now(Team::Enemy, 7, 5, None));
+        let rows = rows(&war);
+        let last = rows.last().unwrap();
+        assert_eq!(last.enemy_penalty, 12);
+        assert_eq!((last.home_total, last.enemy_total), war.totals());
+    }
+
+    #[test]
+    fn csv_fields_stay_text() {
+        assert_eq!(csv_field("ABC"), "ABC");
+        assert_eq!(csv_field("A,B"), "\"A,B\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("=1+1"), "'=1+1");
+        assert_eq!(csv_field("-CD,x"), "\"'-CD,x\"");
+        assert_eq!(csv_field("@x"), "'@x");
+    }
+}
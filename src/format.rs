@@ -0,0 +1,267 @@
+use crate::error::WarError;
+use serde::{Deserialize, Serialize};
+
+/// Points by finishing position in a 12 player Mario Kart 8 Deluxe room.
+pub const POINTS_12: [u32; 12] = [15, 12, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1];
+
+pub const MAX_TEAM_SIZE: u32 = 6;
+pub const MAX_RACES: u32 = 32;
+
+/// The shape of a war: who plays, for how long and for how many points.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct WarFormat {
+    /// Players per team; the room holds two teams.
+    pub team_size: u32,
+    /// Regulation races.
+    pub races: u32,
+    /// Points by finishing position, first place first, one entry per
+    /// player in the room.
+    pub points: Vec<u32>,
+    pub tiebreak: Tiebreak,
+}
+
+/// What happens once regulation is over.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Tiebreak {
+    /// Extra races that may be played after regulation.
+    pub extra_races: u32,
+}
+
+impl Default for WarFormat {
+    /// The classic 12 race 6v6.
+    fn default() -> Self {
+        Self::preset(MAX_TEAM_SIZE)
+    }
+}
+
+impl WarFormat {
+    /// 12 races of `team_size`v`team_size`, scored with the top of the 12
+    /// player table.
+    fn preset(team_size: u32) -> Self {
+        Self {
+            team_size,
+            races: 12,
+            points: POINTS_12[..2 * team_size as usize].to_vec(),
+            tiebreak: Tiebreak { extra_races: 4 },
+        }
+    }
+
+    /// Points both teams share each race.
+    pub fn race_points(&self) -> u32 {
+        self.points.iter().sum()
+    }
+
+    /// The largest diff one race can produce: one team takes the top
+    /// `team_size` positions. Stored formats that never went through
+    /// [`FormatInput::build`] may not make sense; they give what they can.
+    pub fn max_race_swing(&self) -> u32 {
+        let split = (self.team_size as usize).min(self.points.len());
+        let (top, bottom) = self.points.split_at(split);
+        top.iter()
+            .sum::<u32>()
+            .saturating_sub(bottom.iter().sum::<u32>())
+    }
+
+    /// Points for finishing at `position` (1-based), if the room has it.
+    pub fn points_for(&self, position: u32) -> Option<u32> {
+        let index = (position as usize).checked_sub(1)?;
+        self.points.get(index).copied()
+    }
+
+    /// Score a race from the home team's finishing positions (1-based); the
+    /// enemy holds every other position in the room.
+    ///
+    /// Returns the home and enemy points.
+    pub fn score_positions(&self, positions: &[u32]) -> Result<(f64, f64), WarError> {
+        let players = self.points.len();
+        if positions.len() != self.team_size as usize {
+            return Err(bad(format!(
+                "expected {} positions, got {}",
+                self.team_size,
+                positions.len()
+            )));
+        }
+
+        let mut home = vec![false; players];
+        for &position in positions {
+            let taken = match (position as usize).checked_sub(1) {
+                Some(i) if i < players => &mut home[i],
+                _ => {
+                    return Err(bad(format!(
+                        "position {position} is outside 1 to {players}"
+                    )))
+                }
+            };
+            if *taken {
+                return Err(bad(format!("position {position} is listed twice")));
+            }
+            *taken = true;
+        }
+
+        let (mut home_points, mut enemy_points) = (0, 0);
+        for (points, is_home) in self.points.iter().zip(home) {
+            if is_home {
+                home_points += points;
+            } else {
+                enemy_points += points;
+            }
+        }
+        Ok((f64::from(home_points), f64::from(enemy_points)))
+    }
+}
+
+/// A format as given when creating a war: either a preset name like
+/// `"4v4"` or individual fields, missing ones taken from the preset for
+/// `team_size` (6v6 if absent).
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum FormatInput {
+    Preset(String),
+    Custom(FormatSpec),
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FormatSpec {
+    team_size: Option<u32>,
+    races: Option<u32>,
+    points: Option<Vec<u32>>,
+    extra_races: Option<u32>,
+}
+
+impl FormatInput {
+    pub fn build(self) -> Result<WarFormat, WarError> {
+        let spec = match self {
+            FormatInput::Custom(spec) => spec,
+            FormatInput::Preset(name) => FormatSpec {
+                team_size: Some(parse_preset(&name)?),
+                ..FormatSpec::default()
+            },
+        };
+
+        let team_size = spec.team_size.unwrap_or(MAX_TEAM_SIZE);
+        if team_size == 0 || team_size > MAX_TEAM_SIZE {
+            return Err(bad(format!(
+                "team_size must be between 1 and {MAX_TEAM_SIZE}"
+            )));
+        }
+        let mut format = WarFormat::preset(team_size);
+
+        if let Some(races) = spec.races {
+            if races == 0 || races > MAX_RACES {
+                return Err(bad(format!("races must be between 1 and {MAX_RACES}")));
+            }
+            format.races = races;
+        }
+        if let Some(points) = spec.points {
+            if points.len() != 2 * team_size as usize {
+                return Err(bad(format!(
+                    "points needs one entry per player ({}), got {}",
+                    2 * team_size,
+                    points.len()
+                )));
+            }
+            if points.windows(2).any(|w| w[0] < w[1]) {
+                return Err(bad("points must not increase with position".to_string()));
+            }
+            format.points = points;
+        }
+        if let Some(extra_races) = spec.extra_races {
+            if extra_races > MAX_RACES {
+                return Err(bad(format!("extra_races must be at most {MAX_RACES}")));
+            }
+            format.tiebreak.extra_races = extra_races;
+        }
+
+        Ok(format)
+    }
+}
+
+/// "4v4" or "4V4" -> 4.
+fn parse_preset(name: &str) -> Result<u32, WarError> {
+    let unknown = || bad(format!("unknown format {name:?}, expected e.g. \"6v6\""));
+    let (home, enemy) = name.split_once(['v', 'V']).ok_or_else(unknown)?;
+    match (home.parse::<u32>(), enemy.parse::<u32>()) {
+        (Ok(home), Ok(enemy)) if home == enemy => Ok(home),
+        _ => Err(unknown()),
+    }
+}
+
+fn bad(reason: String) -> WarError {
+    WarError::BadRequest(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(name: &str) -> Result<WarFormat, WarError> {
+        FormatInput::Preset(name.to_string()).build()
+    }
+
+    #[test]
+    fn presets_ignore_case() {
+        assert_eq!(preset("2v2").unwrap(), preset("2V2").unwrap());
+        assert_eq!(preset("2v2").unwrap().points, vec![15, 12, 10, 9]);
+        assert!(preset("2v3").is_err());
+        assert!(preset("7v7").is_err());
+        assert!(preset("six").is_err());
+    }
+
+    #[test]
+    fn custom_formats_are_checked() {
+        let custom =
+            |spec: serde_json::Value| serde_json::from_value::<FormatInput>(spec).unwrap().build();
+        let format = custom(serde_json::json!({"team_size": 2, "races": 8})).unwrap();
+        assert_eq!((format.team_size, format.races), (2, 8));
+        assert!(custom(serde_json::json!({"races": 0})).is_err());
+        assert!(custom(serde_json::json!({"team_size": 1, "points": [1, 2]})).is_err());
+        assert!(custom(serde_json::json!({"team_size": 1, "points": [2]})).is_err());
+    }
+
+    #[test]
+    fn score_positions_splits_the_room() {
+        let format = WarFormat::default();
+        assert_eq!(
+            format.score_positions(&[1, 2, 3, 4, 5, 6]),
+            Ok((61.0, 21.0))
+        );
+        assert_eq!(
+            format.score_positions(&[7, 8, 9, 10, 11, 12]),
+            Ok((21.0, 61.0))
+        );
+        assert_eq!(
+            format.score_positions(&[1, 3, 5, 7, 9, 11]),
+            Ok((45.0, 37.0))
+        );
+        assert_eq!(
+            preset("2v2").unwrap().score_positions(&[1, 4]),
+            Ok((24.0, 22.0))
+        );
+    }
+
+    #[test]
+    fn score_positions_rejects_bad_positions() {
+        let format = WarFormat::default();
+        assert!(format.score_positions(&[1, 2, 3, 4, 5]).is_err());
+        assert!(format.score_positions(&[1, 1, 3, 4, 5, 6]).is_err());
+        assert!(format.score_positions(&[0, 2, 3, 4, 5, 6]).is_err());
+        assert!(format.score_positions(&[1, 2, 3, 4, 5, 13]).is_err());
+    }
+
+    #[test]
+    fn max_race_swing_survives_unbuilt_formats() {
+        assert_eq!(WarFormat::default().max_race_swing(), 40);
+        let short = WarFormat {
+            points: vec![15, 12],
+            ..WarFormat::default()
+        };
+        assert_eq!(short.max_race_swing(), 27);
+        let upside_down = WarFormat {
+            team_size: 1,
+            points: vec![1, 15],
+            ..WarFormat::default()
+        };
+        assert_eq!(upside_down.max_race_swing(), 0);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::error::WarError;
+use crate::migrate;
+use crate::war::{unix_now, WarData};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Revisions kept per channel; the oldest are dropped first.
+pub const MAX_REVISIONS: usize = 100;
+
+/// Action of the revision holding a war that predates its history.
+pub const BEFORE_HISTORY: &str = "before history";
+
+/// Action of a revision holding a war written around the history, e.g. by
+/// a bot's `PUT`.
+pub const EXTERNAL_WRITE: &str = "external write";
+
+/// The war as it was after one change.
+#[derive(Clone)]
+pub struct Revision {
+    /// Unix seconds.
+    pub at: Option<u64>,
+    /// What changed, e.g. "add race".
+    pub action: String,
+    pub war: WarData,
+}
+
+#[derive(Serialize)]
+struct Encoded<'a> {
+    at: Option<u64>,
+    action: &'a str,
+    war: Value,
+}
+
+#[derive(Deserialize)]
+struct Decoded {
+    at: Option<u64>,
+    action: String,
+    war: Value,
+}
+
+impl Revision {
+    pub fn new(action: &str, war: WarData) -> Self {
+        Self {
+            at: unix_now(),
+            action: action.to_owned(),
+            war,
+        }
+    }
+
+    /// Serialize with the war in the current stored format, so revisions
+    /// are upgraded on read like the war itself.
+    pub fn encode(&self) -> String {
+        serde_json::to_string(&Encoded {
+            at: self.at,
+            action: &self.action,
+            war: migrate::to_value(&self.war),
+        })
+        .unwrap()
+    }
+
+    pub fn decode(json: &str) -> Result<Self, WarError> {
+        let decoded: Decoded = serde_json::from_str(json).map_err(WarError::from_json)?;
+        Ok(Self {
+            at: decoded.at,
+            action: decoded.action,
+            war: migrate::upgrade(decoded.war)?.war,
+        })
+    }
+}
+
+/// Which way to move through a channel's history.
+#[derive(Clone, Copy)]
+pub enum Step {
+    Undo,
+    Redo,
+}
+
+impl Step {
+    /// The revision to go live instead of `cursor`, out of `len`.
+    pub fn target(self, cursor: usize, len: usize) -> Result<usize, WarError> {
+        let target = match self {
+            Step::Undo => cursor.checked_sub(1),
+            Step::Redo => Some(cursor + 1).filter(|&next| next < len),
+        };
+        target.ok_or_else(|| {
+            WarError::Conflict(match self {
+                Step::Undo => "nothing to undo".to_string(),
+                Step::Redo => "nothing to redo".to_string(),
+            })
+        })
+    }
+}
+
+/// A channel's revisions, oldest first, and which one is live.
+#[derive(Clone, Default)]
+pub struct History {
+    pub revisions: Vec<Revision>,
+    /// Index of the live revision; revisions after it were undone.
+    pub cursor: usize,
+}
+
+impl History {
+    /// Make `revision` the live one, dropping anything undone. `previous` is
+    /// the war being replaced; it starts an empty history so the first
+    /// change can be undone too, and is kept as an external write if it
+    /// isn't the live revision.
+    pub fn record(&mut self, previous: Option<&WarData>, revision: Revision) {
+        if self.revisions.is_empty() {
+            if let Some(previous) = previous {
+                self.revisions
+                    .push(Revision::new(BEFORE_HISTORY, previous.clone()));
+            }
+        } else {
+            self.revisions.truncate(self.cursor + 1);
+            if let Some(previous) = self.external(previous) {
+                self.push(Revision::new(EXTERNAL_WRITE, previous.clone()));
+            }
+        }
+        self.push(revision);
+    }
+
+    /// Move the cursor and return the war that is now live. `live` is the
+    /// stored war; if it was written around the history it is recorded
+    /// first, so undo returns to the revision before it instead of losing it.
+    pub fn step(&mut self, live: Option<&WarData>, step: Step) -> Result<&WarData, WarError> {
+        if let Some(live) = self.external(live) {
+            // Recording it drops anything undone, leaving only undo.
+            step.target(self.cursor + 1, self.cursor + 2)?;
+            self.revisions.truncate(self.cursor + 1);
+            self.push(Revision::new(EXTERNAL_WRITE, live.clone()));
+        }
+        self.cursor = step.target(self.cursor, self.revisions.len())?;
+        Ok(&self.revisions[self.cursor].war)
+    }
+
+    /// `live` if it differs from the live revision's war.
+    fn external<'a>(&self, live: Option<&'a WarData>) -> Option<&'a WarData> {
+        let recorded = &self.revisions.get(self.cursor)?.war;
+        live.filter(|live| *live != recorded)
+    }
+
+    fn push(&mut self, revision: Revision) {
+        self.revisions.push(revision);
+        let excess = self.revisions.len().saturating_sub(MAX_REVISIONS);
+        self.revisions.drain(..excess);
+        self.cursor = self.revisions.len() - 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::war;
+
+    fn races(history: &History) -> Vec<usize> {
+        history
+            .revisions
+            .iter()
+            .map(|revision| revision.war.race_count())
+            .collect()
+    }
+
+    #[test]
+    fn record_keeps_the_war_it_replaces() {
+        let mut history = History::default();
+        history.record(Some(&war(0)), Revision::new("add race", war(1)));
+        assert_eq!(history.revisions[0].action, BEFORE_HISTORY);
+        assert_eq!(races(&history), vec![0, 1]);
+        assert_eq!(history.cursor, 1);
+
+        history.record(Some(&war(1)), Revision::new("add race", war(2)));
+        assert_eq!(races(&history), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = History::default();
+        history.record(None, Revision::new("create war", war(0)));
+        history.record(Some(&war(0)), Revision::new("add race", war(1)));
+
+        assert!(history.step(Some(&war(1)), Step::Redo).is_err());
+        let undone = history.step(Some(&war(1)), Step::Undo).unwrap();
+        assert_eq!(undone.race_count(), 0);
+        assert!(matches!(
+            history.step(Some(&war(0)), Step::Undo),
+            Err(WarError::Conflict(_))
+        ));
+        let redone = history.step(Some(&war(0)), Step::Redo).unwrap();
+        assert_eq!(redone.race_count(), 1);
+    }
+
+    #[test]
+    fn recording_drops_what_was_undone() {
+        let mut history = History::default();
+        history.record(None, Revision::new("create war", war(0)));
+        history.record(Some(&war(0)), Revision::new("add race", war(1)));
+        history.record(Some(&war(1)), Revision::new("add race", war(2)));
+        history.step(Some(&war(2)), Step::Undo).unwrap();
+        history.step(Some(&war(1)), Step::Undo).unwrap();
+
+        history.record(Some(&war(0)), Revision::new("add race", war(3)));
+        assert_eq!(races(&history), vec![0, 3]);
+        assert!(history.step(Some(&war(3)), Step::Redo).is_err());
+    }
+
+    #[test]
+    fn writes_around_the_history_are_kept() {
+        let mut history = History::default();
+        history.record(None, Revision::new("create war", war(0)));
+        history.record(Some(&war(5)), Revision::new("add race", war(6)));
+        assert_eq!(races(&history), vec![0, 5, 6]);
+        assert_eq!(history.revisions[1].action, EXTERNAL_WRITE);
+    }
+
+    #[test]
+    fn undo_returns_to_before_a_write_around_the_history() {
+        let mut history = History::default();
+        history.record(None, Revision::new("create war", war(0)));
+        history.record(Some(&war(0)), Revision::new("add race", war(1)));
+
+        assert!(history.step(Some(&war(4)), Step::Redo).is_err());
+        assert_eq!(races(&history), vec![0, 1]);
+
+        let undone = history.step(Some(&war(4)), Step::Undo).unwrap();
+        assert_eq!(undone.race_count(), 1);
+        assert_eq!(races(&history), vec![0, 1, 4]);
+        let redone = history.step(Some(&war(1)), Step::Redo).unwrap();
+        assert_eq!(redone.race_count(), 4);
+    }
+
+    #[test]
+    fn keeps_the_newest_revisions() {
+        let mut history = History::default();
+        for races in 0..MAX_REVISIONS + 5 {
+            history.record(None, Revision::new("add race", war(races)));
+        }
+        assert_eq!(history.revisions.len(), MAX_REVISIONS);
+        assert_eq!(history.revisions[0].war.race_count(), 5);
+        assert_eq!(history.cursor, MAX_REVISIONS - 1);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::archive;
+use crate::error::WarError;
+use crate::store::WarStore;
+use crate::validate::{self, Policy};
+use crate::war::OverlayData;
+use actix_web::rt;
+use log::info;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::task::AbortHandle;
+
+/// How many snapshots a slow session may fall behind before it skips ahead.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// The war as viewers should currently see it, or why they can't.
+pub type Snapshot = Result<OverlayData, WarError>;
+
+struct Entry {
+    tx: broadcast::Sender<Snapshot>,
+    /// Last snapshot sent on `tx`, `None` until the first fetch completes.
+    latest: Option<Snapshot>,
+    task: AbortHandle,
+}
+
+/// Shares one upstream watcher per channel between all websocket sessions.
+///
+/// The watcher task is spawned by the first subscriber and aborted when the
+/// last [`Subscription`] for that channel is dropped.
+#[derive(Clone)]
+pub struct Hub {
+    store: Arc<dyn WarStore>,
+    policy: Policy,
+    channels: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl Hub {
+    pub fn new(store: Arc<dyn WarStore>, policy: Policy) -> Self {
+        Self {
+            store,
+            policy,
+            channels: Arc::default(),
+        }
+    }
+
+    /// Join the channel's fan-out, starting its watcher if needed.
+    ///
+    /// Also returns the snapshot current at the time of joining, if the
+    /// watcher has fetched one yet; anything later arrives on the
+    /// subscription.
+    pub fn subscribe(&self, channel_id: &str) -> (Option<Snapshot>, Subscription) {
+        let mut channels = self.channels.lock().unwrap();
+        let entry = channels.entry(channel_id.to_owned()).or_insert_with(|| {
+            info!(target: channel_id, "starting hub");
+            let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+            let task = rt::spawn(self.clone().feed(channel_id.to_owned()));
+            Entry {
+                tx,
+                latest: None,
+                task: task.abort_handle(),
+            }
+        });
+
+        let subscription = Subscription {
+            rx: entry.tx.subscribe(),
+            hub: self.clone(),
+            channel_id: channel_id.to_owned(),
+        };
+        (entry.latest.clone(), subscription)
+    }
+
+    /// Fetch the war whenever it may have changed and publish it if it did.
+    ///
+    /// Also archives the wars it reads, which catches the ones the bot
+    /// finishes without going through the API.
+    async fn feed(self, channel_id: String) {
+        let mut watch = self.store.watch(&channel_id).await;
+        loop {
+            let war = self.store.get(&channel_id).await;
+            let data = war
+                .clone()
+                .and_then(|war| validate::overlay(war, self.policy));
+            {
+                let mut channels = self.channels.lock().unwrap();
+                let Some(entry) = channels.get_mut(&channel_id) else {
+                    return;
+                };
+                if entry.latest.as_ref() != Some(&data) {
+                    let _ = entry.tx.send(data.clone());
+                    entry.latest = Some(data);
+                }
+            }
+            if let Ok(war) = &war {
+                archive::keep(self.store.as_ref(), &channel_id, war).await;
+            }
+            watch.changed().await;
+        }
+    }
+}
+
+/// A session's view of a channel's fan-out.
+pub struct Subscription {
+    rx: broadcast::Receiver<Snapshot>,
+    hub: Hub,
+    channel_id: String,
+}
+
+impl Subscription {
+    /// The most recent snapshot, if the watcher has fetched one yet.
+    pub fn latest(&self) -> Option<Snapshot> {
+        let channels = self.hub.channels.lock().unwrap();
+        channels.get(&self.channel_id)?.latest.clone()
+    }
+
+    /// Wait for the next snapshot.
+    ///
+    /// A session that lagged behind gets the latest snapshot instead of the
+    /// ones it missed. Returns `None` if the hub went away.
+    pub async fn recv(&mut self) -> Option<Snapshot> {
+        match self.rx.recv().await {
+            Ok(v) => Some(v),
+            Err(broadcast::error::RecvError::Lagged(_)) => {
+                self.rx = self.rx.resubscribe();
+                self.latest()
+            }
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let mut channels = self.hub.channels.lock().unwrap();
+        // `self.rx` is still alive here, so the last subscriber sees a count of
+        // one. Holding the lock keeps a concurrent `subscribe` from sneaking in.
+        let last = match channels.get(&self.channel_id) {
+            Some(entry) => entry.tx.receiver_count() <= 1,
+            None => false,
+        };
+        if last {
+            if let Some(entry) = channels.remove(&self.channel_id) {
+                entry.task.abort();
+                info!(target: &self.channel_id, "stopping hub");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+    use crate::testing::war;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn hub() -> (Arc<dyn WarStore>, Hub) {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        (store.clone(), Hub::new(store, Policy::Warn))
+    }
+
+    async fn next(subscription: &mut Subscription) -> Snapshot {
+        timeout(Duration::from_secs(1), subscription.recv())
+            .await
+            .expect("no snapshot")
+            .expect("hub went away")
+    }
+
+    #[actix_web::test]
+    async fn publishes_the_war_and_its_changes() {
+        let (store, hub) = hub();
+        store.put("ch", &war(1)).await.unwrap();
+        let (_, mut subscription) = hub.subscribe("ch");
+        assert_eq!(next(&mut subscription).await.unwrap().score, 50);
+
+        store.put("ch", &war(2)).await.unwrap();
+        assert_eq!(next(&mut subscription).await.unwrap().score, 100);
+        assert_eq!(subscription.latest().unwrap().unwrap().score, 100);
+    }
+
+    #[actix_web::test]
+    async fn missing_wars_are_snapshots_too() {
+        let (_, hub) = hub();
+        let (_, mut subscription) = hub.subscribe("ch");
+        assert!(matches!(
+            next(&mut subscription).await,
+            Err(WarError::NotFound)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn viewers_share_one_watcher_until_the_last_leaves() {
+        let (store, hub) = hub();
+        store.put("ch", &war(1)).await.unwrap();
+        let (_, mut first) = hub.subscribe("ch");
+        next(&mut first).await.unwrap();
+
+        let (initial, second) = hub.subscribe("ch");
+        assert_eq!(initial.unwrap().unwrap().score, 50);
+        assert_eq!(hub.channels.lock().unwrap().len(), 1);
+
+        drop(first);
+        assert!(hub.channels.lock().unwrap().contains_key("ch"));
+        drop(second);
+        assert!(hub.channels.lock().unwrap().is_empty());
+    }
+}
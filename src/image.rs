@@ -0,0 +1,612 @@
+use crate::config::Config;
+use crate::error::WarError;
+use crate::palette::{CHALK, CHALK_DIM, GLASS, INK, LEAD, LOSS, PEN, STROKE, TRAIL, WIN};
+use crate::store::WarStore;
+use crate::track::Track;
+use crate::validate;
+use crate::war::{OverlayData, Team};
+use crate::{badge, escape, races_label, TOP_SCORERS};
+use actix_web::error::ErrorInternalServerError;
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use resvg::tiny_skia::{Pixmap, Transform};
+use resvg::usvg::{fontdb, Options, Tree};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::{Arc, OnceLock};
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 330.0;
+const FONT: &str = "'Saira Condensed', 'Arial Narrow', sans-serif";
+
+/// Left and right edge of the content.
+const MARGIN: f64 = 40.0;
+/// Distance of each score from the middle, leaving room for the diff.
+const SCORE_GAP: f64 = 56.0;
+/// Widest a tag is drawn before it gets squeezed.
+const TAG_WIDTH: f64 = 120.0;
+
+/// Zero line of the diff bars and the height of a bar for the largest
+/// possible swing.
+const BAR_BASELINE: f64 = 195.0;
+const BAR_REACH: f64 = 60.0;
+
+/// Draw a war's result: tags, scores, penalties and a diff bar per race.
+/// `max_swing` is the largest diff one race can produce, so bars compare
+/// across wars of the same format.
+pub fn svg(data: &OverlayData, max_swing: u32) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="{FONT}" font-weight="700">
+<rect x="0.5" y="0.5" width="{}" height="{}" rx="16" fill="{INK}" stroke="{STROKE}"/>
+"#,
+        WIDTH - 1.0,
+        HEIGHT - 1.0,
+    );
+
+    header(&mut out, data);
+    bars(&mut out, data, max_swing);
+
+    let _ = write!(
+        out,
+        r#"<text x="{}" y="{}" text-anchor="middle" font-size="14" letter-spacing="1.5" fill="{CHALK_DIM}">{}</text>
+</svg>
+"#,
+        WIDTH / 2.0,
+        HEIGHT - 18.0,
+        races_label(data),
+    );
+    out
+}
+
+/// Tags, scores, the diff between them and penalties under the scores.
+fn header(out: &mut String, data: &OverlayData) {
+    let center = WIDTH / 2.0;
+    tag(out, &data.tag, MARGIN, "start");
+    tag(out, &data.enemy_tag, WIDTH - MARGIN, "end");
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="74" text-anchor="end" font-size="46" fill="{LEAD}">{}</text>"#,
+        center - SCORE_GAP,
+        data.score
+    );
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="74" text-anchor="start" font-size="46" fill="{TRAIL}">{}</text>"#,
+        center + SCORE_GAP,
+        data.enemy_score
+    );
+
+    let (pod, ink, text) = match data.diff {
+        d if d > 0 => (LEAD, INK, format!("+{d}")),
+        d if d < 0 => (TRAIL, INK, d.to_string()),
+        _ => (STROKE, CHALK, "0".to_string()),
+    };
+    let width = (16.0 + 14.0 * text.len() as f64).max(64.0);
+    let _ = writeln!(
+        out,
+        r#"<rect x="{}" y="38" width="{width}" height="40" rx="10" fill="{pod}"/>
+<text x="{center}" y="66" text-anchor="middle" font-size="22" fill="{ink}">{text}</text>"#,
+        center - width / 2.0,
+    );
+
+    for (pen, x, anchor) in [
+        (data.home_pen, center - SCORE_GAP, "end"),
+        (data.enemy_pen, center + SCORE_GAP, "start"),
+    ] {
+        if pen > 0 {
+            let _ = writeln!(
+                out,
+                r#"<text x="{x}" y="100" text-anchor="{anchor}" font-size="14" letter-spacing="1" fill="{PEN}">PEN -{pen}</text>"#
+            );
+        }
+    }
+}
+
+/// Attributes squeezing `text` into `width` if it would overflow, given
+/// roughly how wide the font sets a character with `spacing` between them.
+/// Squeezed text drops the spacing, which would stop it from fitting.
+fn fit(text: &str, per_char: f64, width: f64, spacing: f64) -> String {
+    if text.chars().count() as f64 * per_char > width {
+        format!(r#" textLength="{width}" lengthAdjust="spacingAndGlyphs""#)
+    } else if spacing > 0.0 {
+        format!(r#" letter-spacing="{spacing}""#)
+    } else {
+        String::new()
+    }
+}
+
+fn tag(out: &mut String, tag: &str, x: f64, anchor: &str) {
+    let squeeze = fit(tag, 18.0, TAG_WIDTH, 0.0);
+    let _ = writeln!(
+        out,
+        r#"<text x="{x}" y="70" text-anchor="{anchor}" font-size="30" fill="{CHALK}"{squeeze}>{}</text>"#,
+        escape(tag)
+    );
+}
+
+/// One bar per race, up for the home team and down for the enemy, with
+/// the races still to play marked on the zero line.
+fn bars(out: &mut String, data: &OverlayData, max_swing: u32) {
+    let races = data.race_diffs.len().max(data.total_races as usize);
+    if races == 0 {
+        return;
+    }
+    let slot = (WIDTH - 2.0 * MARGIN) / races as f64;
+    let width = slot * 0.7;
+    let scale = BAR_REACH / f64::from(max_swing.max(1));
+
+    let _ = writeln!(
+        out,
+        r#"<line x1="{MARGIN}" y1="{BAR_BASELINE}" x2="{}" y2="{BAR_BASELINE}" stroke="{STROKE}"/>"#,
+        WIDTH - MARGIN
+    );
+    for i in 0..races {
+        let x = MARGIN + slot * i as f64 + (slot - width) / 2.0;
+        let middle = x + width / 2.0;
+        let Some(&diff) = data.race_diffs.get(i) else {
+            let _ = writeln!(
+                out,
+                r#"<rect x="{x:.1}" y="{}" width="{width:.1}" height="2" fill="{STROKE}"/>"#,
+                BAR_BASELINE - 1.0
+            );
+            continue;
+        };
+
+        let height = (f64::from(diff.unsigned_abs()) * scale).clamp(2.0, BAR_REACH);
+        let (top, fill) = match diff {
+            d if d > 0 => (BAR_BASELINE - height, LEAD),
+            d if d < 0 => (BAR_BASELINE, TRAIL),
+            _ => (BAR_BASELINE - 1.0, CHALK_DIM),
+        };
+        let _ = writeln!(
+            out,
+            r#"<rect x="{x:.1}" y="{top:.1}" width="{width:.1}" height="{height:.1}" rx="2" fill="{fill}"/>"#
+        );
+
+        if slot >= 24.0 {
+            let (y, text) = match diff {
+                d if d > 0 => (top - 5.0, format!("+{d}")),
+                d if d < 0 => (top + height + 13.0, d.to_string()),
+                _ => (top - 5.0, "0".to_string()),
+            };
+            let _ = writeln!(
+                out,
+                r#"<text x="{middle:.1}" y="{y:.1}" text-anchor="middle" font-size="11" fill="{CHALK}">{text}</text>"#
+            );
+        }
+    }
+
+    // Race numbers, or courses where there is room for them.
+    if slot < 14.0 {
+        return;
+    }
+    for i in 0..races {
+        let middle = MARGIN + slot * (i as f64 + 0.5);
+        let label = match data.tracks.get(i).copied().flatten() {
+            Some(track) if slot >= 30.0 => track.abbr().to_string(),
+            _ => (i + 1).to_string(),
+        };
+        let _ = writeln!(
+            out,
+            r#"<text x="{middle:.1}" y="{}" text-anchor="middle" font-size="11" fill="{CHALK_DIM}">{label}</text>"#,
+            BAR_BASELINE + BAR_REACH + 26.0
+        );
+    }
+}
+
+/// The channel's war as a standalone SVG, e.g. for posting results.
+#[get("/table/{channel_id}.svg")]
+async fn table_svg(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let war = store.get(&path.into_inner()).await?;
+    let max_swing = war.format.max_race_swing();
+    let data = validate::overlay(war, config.validation)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/svg+xml")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(svg(&data, max_swing)))
+}
+
+/// Font of the overlay's tags, scores and diff, as the stylesheet names
+/// it; PNGs fall back to the bundled fonts.
+const DISPLAY_FONT: &str = "'Titan One', 'Arial Rounded MT Bold', sans-serif";
+
+/// Room above the scoreboard for the badge and penalties, and around it.
+const BOARD_TOP: f64 = 44.0;
+const BOARD_MARGIN: f64 = 8.0;
+
+/// Sizes from the overlay's stylesheet, in CSS pixels.
+const BOARD_PAD_X: f64 = 22.0;
+const BOARD_PAD_TOP: f64 = 12.0;
+const BOARD_PAD_BOTTOM: f64 = 11.0;
+const BOARD_GAP: f64 = 14.0;
+const BOARD_TAG_WIDTH: f64 = 132.0;
+const BOARD_SCORE_WIDTH: f64 = 104.0;
+const BOARD_POD_WIDTH: f64 = 92.0;
+const BOARD_ROW: f64 = 57.0;
+const STRIP_MARGIN: f64 = 9.0;
+const STRIP_ROW: f64 = 19.0;
+const STRIP_GAP: f64 = 12.0;
+const STRIP_LABEL_WIDTH: f64 = 118.0;
+const PIP_WIDTH: f64 = 24.0;
+const PIP_HEIGHT: f64 = 12.0;
+const PIP_GAP: f64 = 5.0;
+const SCORERS_MARGIN: f64 = 7.0;
+const SCORERS_ROW: f64 = 18.0;
+
+/// The overlay's scoreboard as an SVG, with the layout, colors and sizes the
+/// browser uses for the same data. The overlay's web fonts aren't bundled,
+/// so rendered text falls back to DejaVu and sets differently.
+pub fn scoreboard(data: &OverlayData) -> String {
+    let diff_text = if data.diff > 0 {
+        format!("+{}", data.diff)
+    } else {
+        data.diff.to_string()
+    };
+    // Roughly how wide the fonts set a character at the pod's size.
+    let pod_width = (28.0 + 17.5 * diff_text.len() as f64).max(BOARD_POD_WIDTH);
+    let main_width = 2.0 * (BOARD_TAG_WIDTH + BOARD_SCORE_WIDTH) + pod_width + 4.0 * BOARD_GAP;
+
+    let total_races = data.total_races as usize;
+    let pips_width = total_races as f64 * (PIP_WIDTH + PIP_GAP) - PIP_GAP;
+    let strip_width = 2.0 * (STRIP_LABEL_WIDTH + STRIP_GAP) + pips_width.max(0.0);
+
+    let scorers: Vec<_> = data.players.iter().take(TOP_SCORERS).collect();
+    let inner_width = main_width.max(strip_width);
+    let panel_width = inner_width + 2.0 * BOARD_PAD_X + 2.0;
+    let mut panel_height = 2.0 + BOARD_PAD_TOP + BOARD_ROW + STRIP_MARGIN + STRIP_ROW;
+    if !scorers.is_empty() {
+        panel_height += SCORERS_MARGIN + SCORERS_ROW;
+    }
+    panel_height += BOARD_PAD_BOTTOM;
+
+    let width = panel_width + 2.0 * BOARD_MARGIN;
+    let height = BOARD_TOP + panel_height + BOARD_MARGIN;
+    let center = width / 2.0;
+    let row_top = BOARD_TOP + 1.0 + BOARD_PAD_TOP;
+    let row_middle = row_top + BOARD_ROW / 2.0;
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="{DISPLAY_FONT}" fill="{CHALK}">
+<defs>
+<pattern id="spent" patternUnits="objectBoundingBox" width="0.5" height="1">
+<rect width="12" height="12" fill="#171B23"/>
+<rect x="6" width="6" height="6" fill="#EDEFEA"/>
+<rect y="6" width="6" height="6" fill="#EDEFEA"/>
+</pattern>
+</defs>
+<rect x="{}" y="{}" width="{}" height="{}" rx="18" fill="{GLASS}" stroke="{STROKE}"/>
+"##,
+        BOARD_MARGIN + 0.5,
+        BOARD_TOP + 0.5,
+        panel_width - 1.0,
+        panel_height - 1.0,
+    );
+
+    // Tags, scores and the diff between them.
+    let mut x = center - main_width / 2.0;
+    board_tag(&mut out, &data.tag, x + BOARD_TAG_WIDTH / 2.0, row_middle);
+    x += BOARD_TAG_WIDTH + BOARD_GAP;
+    let home_score = x + BOARD_SCORE_WIDTH / 2.0;
+    x += BOARD_SCORE_WIDTH + BOARD_GAP;
+    let pod_left = x;
+    x += pod_width + BOARD_GAP;
+    let enemy_score = x + BOARD_SCORE_WIDTH / 2.0;
+    x += BOARD_SCORE_WIDTH + BOARD_GAP;
+    board_tag(
+        &mut out,
+        &data.enemy_tag,
+        x + BOARD_TAG_WIDTH / 2.0,
+        row_middle,
+    );
+    for (score, x) in [(data.score, home_score), (data.enemy_score, enemy_score)] {
+        let _ = writeln!(
+            out,
+            r#"<text x="{x}" y="{row_middle}" text-anchor="middle" dominant-baseline="central" font-size="52">{score}</text>"#
+        );
+    }
+
+    let pod_height = 41.0;
+    let pod_top = row_middle - pod_height / 2.0;
+    let (pod_fill, pod_ink) = match data.diff {
+        d if d > 0 => (LEAD, INK),
+        d if d < 0 => (TRAIL, INK),
+        _ => ("rgba(247, 248, 244, 0.12)", CHALK),
+    };
+    let _ = writeln!(
+        out,
+        r#"<rect x="{pod_left}" y="{pod_top}" width="{pod_width}" height="{pod_height}" rx="12" fill="{pod_fill}"/>
+<text x="{}" y="{row_middle}" text-anchor="middle" dominant-baseline="central" font-size="25" fill="{pod_ink}">{diff_text}</text>"#,
+        pod_left + pod_width / 2.0,
+    );
+    // The arrow on the leading side of the pod.
+    let arrow = match data.diff {
+        d if d > 0 => Some((pod_left, -7.0, LEAD)),
+        d if d < 0 => Some((pod_left + pod_width, 7.0, TRAIL)),
+        _ => None,
+    };
+    if let Some((base, reach, fill)) = arrow {
+        let _ = writeln!(
+            out,
+            r#"<path d="M{base} {} L{} {row_middle} L{base} {} Z" fill="{fill}"/>"#,
+            row_middle - 7.0,
+            base + reach,
+            row_middle + 7.0,
+        );
+    }
+
+    for (pen, x) in [(data.home_pen, home_score), (data.enemy_pen, enemy_score)] {
+        if pen > 0 {
+            pill(
+                &mut out,
+                &format!("PEN -{pen}"),
+                x,
+                row_top,
+                PEN,
+                "#fff",
+                Some(GLASS),
+            );
+        }
+    }
+    let (badge_text, badge_class) = badge(data);
+    if !badge_text.is_empty() {
+        let (fill, ink) = match badge_class {
+            "home" => (LEAD, INK),
+            "enemy" => (TRAIL, INK),
+            _ => (GLASS, CHALK),
+        };
+        pill(
+            &mut out,
+            &badge_text,
+            center,
+            BOARD_TOP - 14.0,
+            fill,
+            ink,
+            None,
+        );
+    }
+
+    // The strip: last course, one pip per regulation race, races left.
+    let strip_middle = row_top + BOARD_ROW + STRIP_MARGIN + STRIP_ROW / 2.0;
+    let strip_left = center - strip_width / 2.0;
+    let last_track = data
+        .tracks
+        .last()
+        .copied()
+        .flatten()
+        .map_or("", Track::abbr);
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{strip_middle}" text-anchor="end" dominant-baseline="central" {}{}>{last_track}</text>"#,
+        strip_left + STRIP_LABEL_WIDTH,
+        label_font(16.0, CHALK_DIM),
+        fit(last_track, 10.5, STRIP_LABEL_WIDTH, 1.0),
+    );
+    let pips_left = strip_left + STRIP_LABEL_WIDTH + STRIP_GAP;
+    let pip_top = strip_middle - PIP_HEIGHT / 2.0;
+    let spent = data.race_diffs.len().min(total_races);
+    for i in 0..total_races {
+        let x = pips_left + i as f64 * (PIP_WIDTH + PIP_GAP);
+        let fill = if i < spent {
+            let underline = match data.race_diffs[i] {
+                d if d > 0 => Some(WIN),
+                d if d < 0 => Some(LOSS),
+                _ => None,
+            };
+            if let Some(underline) = underline {
+                let _ = writeln!(
+                    out,
+                    r#"<rect x="{x}" y="{}" width="{PIP_WIDTH}" height="{PIP_HEIGHT}" rx="3" fill="{underline}"/>"#,
+                    pip_top + 1.0
+                );
+            }
+            "url(#spent)"
+        } else {
+            "rgba(247, 248, 244, 0.22)"
+        };
+        let _ = writeln!(
+            out,
+            r#"<rect x="{x}" y="{pip_top}" width="{PIP_WIDTH}" height="{PIP_HEIGHT}" rx="3" fill="{fill}"/>"#
+        );
+    }
+    let races = races_label(data);
+    let _ = writeln!(
+        out,
+        r#"<text x="{}" y="{strip_middle}" dominant-baseline="central" {}{}>{races}</text>"#,
+        pips_left + pips_width.max(0.0) + STRIP_GAP,
+        label_font(16.0, CHALK_DIM),
+        fit(&races, 11.5, STRIP_LABEL_WIDTH, 2.2),
+    );
+
+    // Top scorers, as one line.
+    if !scorers.is_empty() {
+        let middle = strip_middle + STRIP_ROW / 2.0 + SCORERS_MARGIN + SCORERS_ROW / 2.0;
+        let _ = write!(
+            out,
+            r#"<text x="{center}" y="{middle}" text-anchor="middle" dominant-baseline="central" {} letter-spacing="0.9">"#,
+            label_font(15.0, CHALK_DIM),
+        );
+        for (i, player) in scorers.iter().enumerate() {
+            let dot = match player.team {
+                Team::Home => LEAD,
+                Team::Enemy => TRAIL,
+            };
+            let space = if i > 0 { "\u{2003}" } else { "" };
+            let _ = write!(
+                out,
+                r#"{space}<tspan fill="{dot}">{DOT}</tspan> {} <tspan fill="{CHALK}">{}</tspan>"#,
+                escape(&player.name),
+                player.score
+            );
+        }
+        out.push_str("</text>\n");
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Marks a scorer's team, like the overlay's colored dot.
+const DOT: char = '\u{25CF}';
+
+/// Attributes of the overlay's condensed labels.
+fn label_font(size: f64, fill: &str) -> String {
+    format!(
+        r#"font-family="{FONT}" font-weight="700" font-stretch="condensed" font-size="{size}" fill="{fill}""#
+    )
+}
+
+/// A tag centered in its cell, squeezed if it would overflow like the
+/// overlay scales it down.
+fn board_tag(out: &mut String, tag: &str, x: f64, y: f64) {
+    let squeeze = fit(tag, 22.0, BOARD_TAG_WIDTH, 0.0);
+    let _ = writeln!(
+        out,
+        r#"<text x="{x}" y="{y}" text-anchor="middle" dominant-baseline="central" font-size="32"{squeeze}>{}</text>"#,
+        escape(tag)
+    );
+}
+
+/// A rounded label centered on `x` and sitting on `bottom`, like the
+/// overlay's badge and penalties.
+fn pill(
+    out: &mut String,
+    text: &str,
+    x: f64,
+    bottom: f64,
+    fill: &str,
+    ink: &str,
+    ring: Option<&str>,
+) {
+    let height = 22.0;
+    // Roughly how wide the label font sets a character, spacing included.
+    let width = 20.0 + 10.5 * text.chars().count() as f64;
+    let stroke = ring.map_or(String::new(), |ring| {
+        format!(r#" stroke="{ring}" stroke-width="3""#)
+    });
+    let _ = writeln!(
+        out,
+        r#"<rect x="{}" y="{}" width="{width}" height="{height}" rx="8" fill="{fill}"{stroke}/>
+<text x="{x}" y="{}" text-anchor="middle" dominant-baseline="central" {} letter-spacing="1.5">{}</text>"#,
+        x - width / 2.0,
+        bottom - height,
+        bottom - height / 2.0,
+        label_font(15.0, ink),
+        escape(text),
+    );
+}
+
+/// Largest width or height of a rendered PNG, in pixels. Larger sizes are
+/// scaled down to fit.
+const MAX_PNG_SIDE: f32 = 4096.0;
+
+#[derive(Deserialize)]
+pub struct PngQuery {
+    /// Width in pixels; the height follows.
+    width: Option<u32>,
+    /// Pixels per CSS pixel, 1 unless `width` is given.
+    scale: Option<f32>,
+}
+
+/// Fonts bundled into the binary, so images look the same everywhere.
+fn fonts() -> Arc<fontdb::Database> {
+    static FONTS: OnceLock<Arc<fontdb::Database>> = OnceLock::new();
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = fontdb::Database::new();
+            fonts.load_font_data(include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf").to_vec());
+            fonts.load_font_data(
+                include_bytes!("../assets/fonts/DejaVuSansCondensed-Bold.ttf").to_vec(),
+            );
+            fonts.set_sans_serif_family("DejaVu Sans");
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+/// Rasterize `svg` at the size `query` asks for, within [`MAX_PNG_SIDE`].
+fn png(svg: &str, query: &PngQuery) -> Result<Vec<u8>, String> {
+    let options = Options {
+        fontdb: fonts(),
+        ..Options::default()
+    };
+    let tree = Tree::from_str(svg, &options).map_err(|e| e.to_string())?;
+    let size = tree.size();
+    let scale = match (query.width, query.scale) {
+        (Some(width), _) => width as f32 / size.width(),
+        (None, scale) => scale.unwrap_or(1.0),
+    }
+    .min(MAX_PNG_SIDE / size.width().max(size.height()));
+    let (width, height) = (size.width() * scale, size.height() * scale);
+    let mut pixmap = Pixmap::new(
+        width.ceil().min(MAX_PNG_SIDE) as u32,
+        height.ceil().min(MAX_PNG_SIDE) as u32,
+    )
+    .ok_or_else(|| format!("cannot allocate a {width}x{height} image"))?;
+    resvg::render(
+        &tree,
+        Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    pixmap.encode_png().map_err(|e| e.to_string())
+}
+
+/// Render off the async workers, refusing sizes nobody needs.
+async fn png_response(svg: String, query: PngQuery) -> Result<HttpResponse> {
+    let scale = query.scale.unwrap_or(1.0);
+    if query.width.is_some() && query.scale.is_some() {
+        return Err(WarError::BadRequest("give width or scale, not both".to_string()).into());
+    }
+    if query.width == Some(0)
+        || query.width.is_some_and(|width| width as f32 > MAX_PNG_SIDE)
+        || !(scale > 0.0 && scale <= 8.0)
+    {
+        return Err(WarError::BadRequest(format!(
+            "width must be between 1 and {MAX_PNG_SIDE} and scale between 0 and 8"
+        ))
+        .into());
+    }
+
+    let png = web::block(move || png(&svg, &query))
+        .await?
+        .map_err(ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("image/png")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(png))
+}
+
+/// The overlay's scoreboard as a PNG, for places that can't run a browser.
+/// A close likeness rather than a copy: see [`scoreboard`].
+#[get("/overlay/{channel_id}.png")]
+async fn overlay_png(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<PngQuery>,
+) -> Result<impl Responder> {
+    let war = store.get(&path.into_inner()).await?;
+    let data = validate::overlay(war, config.validation)?;
+    png_response(scoreboard(&data), query.into_inner()).await
+}
+
+/// [`table_svg`] as a PNG.
+#[get("/table/{channel_id}.png")]
+async fn table_png(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<PngQuery>,
+) -> Result<impl Responder> {
+    let war = store.get(&path.into_inner()).await?;
+    let max_swing = war.format.max_race_swing();
+    let data = validate::overlay(war, config.validation)?;
+    png_response(svg(&data, max_swing), query.into_inner()).await
+}
@@ -1,101 +1,53 @@
+mod api;
+mod archive;
+mod auth;
+mod config;
+mod error;
+mod export;
+mod format;
+mod history;
+mod hub;
+mod image;
+mod memory_store;
+mod migrate;
+mod palette;
+mod redis_store;
+mod replay;
+mod stats;
+mod store;
+mod table;
+#[cfg(test)]
+mod testing;
+mod track;
+mod updates;
+mod validate;
+mod war;
+
 use actix_web::{get, rt, web, App, HttpRequest, HttpResponse, HttpServer, Responder, Result};
 use actix_ws::AggregatedMessage;
+use config::{Backend, Config};
+use format::WarFormat;
 use futures_util::StreamExt;
-use log::{error, info};
-use redis::Commands;
-use serde::{Deserialize, Serialize};
-use std::time::{Duration, Instant};
+use hub::{Hub, Snapshot};
+use memory_store::MemoryStore;
+use redis_store::RedisStore;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+use store::WarStore;
 use tokio::time::interval;
+use track::Track;
+use war::{Clinched, OverlayData, Phase, Team};
 
-#[derive(Serialize, Deserialize)]
-struct WarData {
-    tag: String,
-    enemy_tag: String,
-    home_score: Vec<f64>,
-    enemy_score: Vec<f64>,
-    diff: Vec<i32>,
-    last_diff: Option<i32>,
-    #[serde(default)]
-    home_pen: i32,
-    #[serde(default)]
-    enemy_pen: i32,
-}
-
-#[derive(Serialize, Clone)]
-struct OverlayData {
-    tag: String,
-    enemy_tag: String,
-    score: i32,
-    enemy_score: i32,
-    diff: i32,
-    last_diff: Option<i32>,
-    race_left: i32,
-    race_diffs: Vec<i32>,
-    home_pen: i32,
-    enemy_pen: i32,
-}
-
-fn query_db(channel_id: String) -> Option<OverlayData> {
-    let client = match redis::Client::open("redis://redis:6379") {
-        Ok(v) => v,
-        Err(e) => {
-            error!(target: &channel_id, "{e}");
-            return None;
-        }
-    };
-    info!(target: &channel_id, "connected to redis");
-
-    let mut con = match client.get_connection() {
-        Ok(v) => v,
-        Err(_) => return None,
-    };
-    info!(target: &channel_id, "connection made to redis");
-
-    let war_data: String = match con.get(&channel_id) {
-        Ok(v) => v,
-        Err(e) => {
-            error!(target: &channel_id, "{e}");
-            return None;
-        }
-    };
-    info!(target: &channel_id, "war data: {war_data}");
-
-    let war_state: WarData = match serde_json::from_str(war_data.as_str()) {
-        Ok(v) => v,
-        Err(e) => {
-            error!(target: &channel_id, "{e}");
-            return None;
-        }
-    };
-    info!(target: &channel_id, "data parsed");
-
-    let race_count = i32::try_from(war_state.diff.len()).unwrap_or(0);
-    let score = war_state.home_score.iter().sum::<f64>().round() as i32 - war_state.home_pen;
-    let enemy_score =
-        war_state.enemy_score.iter().sum::<f64>().round() as i32 - war_state.enemy_pen;
-    let diff = score - enemy_score;
-    let last_diff = war_state.diff.iter().last().copied();
-    let race_left = match 12 - race_count {
-        v if v < 0 && v > -4 => v + 4,
-        v if v <= -4 => 0,
-        v => v,
-    };
-
-    let res = OverlayData {
-        tag: war_state.tag,
-        enemy_tag: war_state.enemy_tag,
-        score,
-        enemy_score,
-        diff,
-        last_diff,
-        race_left,
-        race_diffs: war_state.diff,
-        home_pen: war_state.home_pen,
-        enemy_pen: war_state.enemy_pen,
-    };
+/// Players shown in the overlay's top scorers strip.
+const TOP_SCORERS: usize = 3;
 
-    Some(res)
-}
+/// The overlay's `<head>`; `/* palette */` is replaced with the colors.
+/// Where the overlay's script opens its websocket, from the last segment of
+/// the page's path.
+const OVERLAY_SOCKET: &str = "'/ws/' + channel";
 
 const OVERLAY_HEAD: &str = r##"<head>
 <meta charset="UTF-8">
@@ -103,18 +55,7 @@ const OVERLAY_HEAD: &str = r##"<head>
 <style>
 @import url('https://fonts.googleapis.com/css2?family=Saira+Condensed:wght@600;700&family=Titan+One&display=swap');
 
-:root {
-  --glass: rgba(13, 16, 23, 0.62);
-  --stroke: rgba(247, 248, 244, 0.16);
-  --chalk: #F7F8F4;
-  --chalk-dim: rgba(247, 248, 244, 0.6);
-  --lead: #FFC530;
-  --trail: #5BC2FF;
-  --pen: #FF5A5F;
-  --win: #4ADE80;
-  --loss: #FF5A5F;
-  --ink: #10131A;
-}
+/* palette */
 
 * { margin: 0; padding: 0; box-sizing: border-box; }
 
@@ -148,6 +89,8 @@ body {
   transition: opacity 0.3s, filter 0.3s;
 }
 
+.panel.fault { border-color: var(--pen); }
+
 .main {
   display: flex;
   align-items: center;
@@ -210,7 +153,6 @@ body {
   gap: 12px;
   margin-top: 9px;
 }
-.strip::before { content: ""; width: 118px; }
 .pips { display: flex; gap: 5px; }
 .pip {
   width: 24px;
@@ -231,6 +173,16 @@ body {
   100% { transform: scaleY(1); }
 }
 
+.track {
+  width: 118px;
+  text-align: right;
+  font-family: 'Saira Condensed', 'Arial Narrow', sans-serif;
+  font-weight: 700;
+  font-size: 16px;
+  letter-spacing: 0.06em;
+  color: var(--chalk-dim);
+}
+
 .races {
   width: 118px;
   font-family: 'Saira Condensed', 'Arial Narrow', sans-serif;
@@ -259,24 +211,102 @@ body {
   animation: pen-in 0.3s ease-out both;
 }
 .pen:empty { display: none; }
+.pen.flash { animation: pen-flash 0.6s ease-in-out 3; }
+@keyframes pen-flash {
+  50% { transform: translateX(-50%) scale(1.15); }
+}
 @keyframes pen-in {
   from { opacity: 0; transform: translate(-50%, 6px); }
   to   { opacity: 1; transform: translate(-50%, 0); }
 }
 
+.scorers {
+  display: flex;
+  justify-content: center;
+  gap: 16px;
+  margin-top: 7px;
+  font-family: 'Saira Condensed', 'Arial Narrow', sans-serif;
+  font-weight: 700;
+  font-size: 15px;
+  letter-spacing: 0.06em;
+  color: var(--chalk-dim);
+}
+.scorers:empty { display: none; }
+.scorer b { color: var(--chalk); font-weight: 700; margin-left: 5px; }
+.scorer::before {
+  content: "";
+  display: inline-block;
+  width: 7px;
+  height: 7px;
+  margin-right: 6px;
+  border-radius: 50%;
+  vertical-align: 2px;
+}
+.scorer.home::before  { background: var(--lead); }
+.scorer.enemy::before { background: var(--trail); }
+
+.badge {
+  position: absolute;
+  left: 50%;
+  bottom: 100%;
+  margin-bottom: 14px;
+  transform: translateX(-50%);
+  font-family: 'Saira Condensed', 'Arial Narrow', sans-serif;
+  font-weight: 700;
+  font-size: 15px;
+  letter-spacing: 0.1em;
+  line-height: 1;
+  padding: 4px 10px 3px;
+  border-radius: 8px;
+  background: var(--glass);
+  border: 1px solid var(--stroke);
+  white-space: nowrap;
+  animation: pen-in 0.3s ease-out both;
+}
+.badge:empty { display: none; }
+.h2h {
+  position: absolute;
+  left: 0;
+  bottom: 100%;
+  margin-bottom: 14px;
+  font-family: 'Saira Condensed', 'Arial Narrow', sans-serif;
+  font-weight: 700;
+  font-size: 15px;
+  letter-spacing: 0.1em;
+  line-height: 1;
+  padding: 4px 10px 3px;
+  border-radius: 8px;
+  background: var(--glass);
+  border: 1px solid var(--stroke);
+  color: var(--chalk-dim);
+  white-space: nowrap;
+  animation: pen-in 0.3s ease-out both;
+}
+.h2h:empty { display: none; }
+.badge.home  { background: var(--lead);  color: var(--ink); border-color: transparent; }
+.badge.enemy { background: var(--trail); color: var(--ink); border-color: transparent; }
+
 @media (prefers-reduced-motion: reduce) {
-  .bug, .pip.just, .pen { animation: none; }
+  .bug, .pip.just, .pen, .pen.flash, .badge, .h2h { animation: none; }
   .panel, .pod, .pod::before, .pod::after { transition: none; }
 }
 </style>
 <script>
+const TOP_SCORERS = 3;
+const PEN_FLASH_MS = 4000;
 const REDUCED = window.matchMedia('(prefers-reduced-motion: reduce)').matches;
-const TOTAL_RACES = 12;
+// Add ?reasons=off to the overlay URL to only ever show penalty totals.
+const FLASH_REASONS = new URLSearchParams(window.location.search).get('reasons') !== 'off';
+// Add ?h2h=on to show the record between the two tags before the first race.
+const SHOW_H2H = new URLSearchParams(window.location.search).get('h2h') === 'on';
 
 let ws;
 let currentData = null;
 let previousScore = 0;
 let previousEnemyScore = 0;
+let penFlashTimer = null;
+let h2hPair = null;
+let h2hRecord = null;
 
 function animateNumber(element, start, end, duration) {
   if (REDUCED || start === end) {
@@ -310,14 +340,66 @@ function setTag(selector, text) {
   fitTag(span);
 }
 
-function racesLabel(left) {
-  if (left === 0) return 'FINAL';
-  return left + (left === 1 ? ' RACE LEFT' : ' RACES LEFT');
+function racesLabel(data) {
+  if (data.phase === 'final') return 'FINAL';
+  if (data.phase === 'tiebreak') {
+    return 'TIEBREAK ' + (data.tiebreak_played + 1) + '/' + data.tiebreak_races;
+  }
+  return data.race_left + (data.race_left === 1 ? ' RACE LEFT' : ' RACES LEFT');
+}
+
+function badge(data) {
+  if (data.phase === 'final') return ['', ''];
+  if (data.clinched !== 'none') return ['CLINCHED', data.clinched];
+  if (data.required_avg_diff > 0) return ['NEEDS +' + data.required_avg_diff + '/RACE', ''];
+  return ['', ''];
+}
+
+function penLabel(pen) {
+  return pen > 0 ? 'PEN -' + pen : '';
+}
+
+function showPenalties(data) {
+  document.querySelector('.pen-home').textContent = penLabel(data.home_pen);
+  document.querySelector('.pen-enemy').textContent = penLabel(data.enemy_pen);
+}
+
+function flashPenalty(penalty) {
+  const el = document.querySelector(penalty.team === 'home' ? '.pen-home' : '.pen-enemy');
+  const amount = penalty.amount > 0 ? '-' + penalty.amount : '+' + (-penalty.amount);
+  el.textContent = penalty.reason.toUpperCase() + ' ' + amount;
+  el.classList.add('flash');
+  clearTimeout(penFlashTimer);
+  penFlashTimer = setTimeout(() => {
+    el.classList.remove('flash');
+    if (currentData) showPenalties(currentData);
+  }, PEN_FLASH_MS);
+}
+
+function updateScorers(players) {
+  const strip = document.querySelector('.scorers');
+  strip.replaceChildren(...(players || []).slice(0, TOP_SCORERS).map(player => {
+    const el = document.createElement('span');
+    el.className = 'scorer ' + player.team;
+    el.textContent = player.name;
+    const score = document.createElement('b');
+    score.textContent = player.score;
+    el.appendChild(score);
+    return el;
+  }));
 }
 
-function updatePips(raceLeft, raceDiffs) {
-  const spent = Math.min(Math.max(TOTAL_RACES - raceLeft, 0), TOTAL_RACES);
+function updatePips(totalRaces, raceDiffs) {
+  const pips = document.querySelector('.pips');
+  while (pips.children.length < totalRaces) {
+    const pip = document.createElement('span');
+    pip.className = 'pip';
+    pips.appendChild(pip);
+  }
+  while (pips.children.length > totalRaces) pips.lastElementChild.remove();
+
   const diffs = raceDiffs || [];
+  const spent = Math.min(diffs.length, totalRaces);
   document.querySelectorAll('.pip').forEach((pip, i) => {
     const wasSpent = pip.classList.contains('spent');
     pip.classList.toggle('spent', i < spent);
@@ -330,6 +412,37 @@ function updatePips(raceLeft, raceDiffs) {
   }, 600);
 }
 
+function h2hLabel(record) {
+  if (!record || record.wars === 0) return '';
+  const draws = record.draws > 0 ? '\u2013' + record.draws : '';
+  return 'H2H ' + record.wins + '\u2013' + record.losses + draws;
+}
+
+function showH2h(data) {
+  const el = document.querySelector('.h2h');
+  el.textContent = data.race_diffs.length === 0 ? h2hLabel(h2hRecord) : '';
+}
+
+function updateH2h(data) {
+  if (!SHOW_H2H) return;
+  const pair = data.tag + '\n' + data.enemy_tag;
+  if (pair !== h2hPair) {
+    h2hPair = pair;
+    h2hRecord = null;
+    const url = '/api/stats/teams/' + encodeURIComponent(data.tag) +
+      '/vs/' + encodeURIComponent(data.enemy_tag);
+    fetch(url)
+      .then(res => res.ok ? res.json() : null)
+      .then(record => {
+        if (pair !== h2hPair) return;
+        h2hRecord = record;
+        if (currentData) showH2h(currentData);
+      })
+      .catch(() => {});
+  }
+  showH2h(data);
+}
+
 function apply(data) {
   setTag('.tag-home .tag-span', data.tag);
   setTag('.tag-enemy .tag-span', data.enemy_tag);
@@ -345,27 +458,49 @@ function apply(data) {
   pod.className = 'pod ' + (data.diff > 0 ? 'plus' : data.diff < 0 ? 'minus' : '');
   pod.textContent = data.diff > 0 ? '+' + data.diff : String(data.diff);
 
-  updatePips(data.race_left, data.race_diffs);
-  document.querySelector('.races').textContent = racesLabel(data.race_left);
-
-  document.querySelector('.pen-home').textContent = data.home_pen > 0 ? 'PEN -' + data.home_pen : '';
-  document.querySelector('.pen-enemy').textContent = data.enemy_pen > 0 ? 'PEN -' + data.enemy_pen : '';
+  updatePips(data.total_races, data.race_diffs);
+  document.querySelector('.races').textContent = racesLabel(data);
+  updateScorers(data.players);
+  const tracks = data.tracks || [];
+  document.querySelector('.track').textContent = tracks[tracks.length - 1] || '';
+
+  const [badgeText, badgeClass] = badge(data);
+  const badgeEl = document.querySelector('.badge');
+  badgeEl.className = 'badge ' + badgeClass;
+  badgeEl.textContent = badgeText;
+
+  updateH2h(data);
+  showPenalties(data);
+  const penalties = data.penalties || [];
+  const seen = currentData ? (currentData.penalties || []).length : null;
+  if (FLASH_REASONS && seen !== null && penalties.length > seen) {
+    const latest = penalties[penalties.length - 1];
+    if (latest.reason) flashPenalty(latest);
+  }
 
   previousScore = data.score;
   previousEnemyScore = data.enemy_score;
   currentData = data;
 }
 
-function handleError() {
+function handleError(code) {
   const panel = document.querySelector('.panel');
   panel.style.opacity = '0.5';
   panel.style.filter = 'grayscale(100%)';
+  panel.classList.toggle('fault', code !== 'no_war');
+  document.querySelector('.races').textContent = code === 'no_war' ? 'NO WAR' : 'SERVER ISSUE';
+  document.querySelector('.badge').textContent = '';
+  document.querySelector('.h2h').textContent = '';
+  document.querySelector('.track').textContent = '';
+  document.querySelector('.scorers').replaceChildren();
+  currentData = null;
 }
 
 function handleDataAvailable() {
   const panel = document.querySelector('.panel');
   panel.style.opacity = '1';
   panel.style.filter = 'none';
+  panel.classList.remove('fault');
 }
 
 function connectWebSocket() {
@@ -376,7 +511,7 @@ function connectWebSocket() {
   ws.onmessage = (event) => {
     const data = JSON.parse(event.data);
     if (data.error) {
-      handleError();
+      handleError(data.code);
       return;
     }
     handleDataAvailable();
@@ -398,55 +533,132 @@ document.addEventListener('DOMContentLoaded', () => {
 </script>
 </head>"##;
 
+#[derive(Deserialize)]
+struct OverlayQuery {
+    /// "on" to show the head-to-head record before the first race.
+    h2h: Option<String>,
+}
+
 #[get("/overlay/{channel_id}")]
-async fn overlay(path: web::Path<String>) -> Result<impl Responder> {
+async fn overlay(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<OverlayQuery>,
+) -> Result<impl Responder> {
     let channel_id = path.into_inner();
-    let json_data = query_db(channel_id);
-
-    let (diff_class, diff_text, race_left, tag, score, enemy_score, enemy_tag, pen_home, pen_enemy) =
-        match &json_data {
-            Some(data) => (
-                if data.diff > 0 {
-                    "plus"
-                } else if data.diff < 0 {
-                    "minus"
-                } else {
-                    ""
-                },
-                if data.diff > 0 {
-                    format!("+{}", data.diff)
-                } else {
-                    data.diff.to_string()
-                },
-                data.race_left,
-                data.tag.as_str(),
-                data.score,
-                data.enemy_score,
-                data.enemy_tag.as_str(),
-                if data.home_pen > 0 {
-                    format!("PEN -{}", data.home_pen)
-                } else {
-                    String::new()
-                },
-                if data.enemy_pen > 0 {
-                    format!("PEN -{}", data.enemy_pen)
-                } else {
-                    String::new()
-                },
-            ),
-            None => ("", "0".to_string(), 12, "...", 0, 0, "...", String::new(), String::new()),
-        };
+    let json_data = store
+        .get(&channel_id)
+        .await
+        .and_then(|war| validate::overlay(war, config.validation))
+        .ok();
+
+    let h2h = match &json_data {
+        Some(data) if query.h2h.as_deref() == Some("on") && data.race_diffs.is_empty() => {
+            stats::head_to_head(store.get_ref(), &data.tag, &data.enemy_tag)
+                .await
+                .map(|record| h2h_label(&record))
+                .unwrap_or_default()
+        }
+        _ => String::new(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(overlay_page(json_data.as_ref(), &h2h)))
+}
+
+/// The overlay as first drawn, showing `json_data` until the websocket takes
+/// over; `h2h` is the head-to-head label, if asked for.
+fn overlay_page(json_data: Option<&OverlayData>, h2h: &str) -> String {
+    let (
+        diff_class,
+        diff_text,
+        races_label,
+        (badge, badge_class),
+        total_races,
+        tag,
+        score,
+        enemy_score,
+        enemy_tag,
+        pen_home,
+        pen_enemy,
+    ) = match json_data {
+        Some(data) => (
+            if data.diff > 0 {
+                "plus"
+            } else if data.diff < 0 {
+                "minus"
+            } else {
+                ""
+            },
+            if data.diff > 0 {
+                format!("+{}", data.diff)
+            } else {
+                data.diff.to_string()
+            },
+            races_label(data),
+            badge(data),
+            data.total_races as usize,
+            escape(&data.tag),
+            data.score,
+            data.enemy_score,
+            escape(&data.enemy_tag),
+            if data.home_pen > 0 {
+                format!("PEN -{}", data.home_pen)
+            } else {
+                String::new()
+            },
+            if data.enemy_pen > 0 {
+                format!("PEN -{}", data.enemy_pen)
+            } else {
+                String::new()
+            },
+        ),
+        None => (
+            "",
+            "0".to_string(),
+            format!("{} RACES LEFT", WarFormat::default().races),
+            (String::new(), ""),
+            WarFormat::default().races as usize,
+            "...".to_string(),
+            0,
+            0,
+            "...".to_string(),
+            String::new(),
+            String::new(),
+        ),
+    };
+
+    let last_track = json_data
+        .and_then(|data| data.tracks.last().copied().flatten())
+        .map_or("", Track::abbr);
+
+    let scorers: String = json_data
+        .iter()
+        .flat_map(|data| data.players.iter().take(TOP_SCORERS))
+        .map(|player| {
+            let team = match player.team {
+                Team::Home => "home",
+                Team::Enemy => "enemy",
+            };
+            format!(
+                r#"<span class="scorer {team}">{}<b>{}</b></span>"#,
+                escape(&player.name),
+                player.score
+            )
+        })
+        .collect();
 
     let race_diffs: &[i32] = json_data
-        .as_ref()
         .map(|data| data.race_diffs.as_slice())
         .unwrap_or(&[]);
 
-    let spent = (12 - race_left).clamp(0, 12);
-    let pips: String = (0..12)
+    let spent = race_diffs.len().min(total_races);
+    let pips: String = (0..total_races)
         .map(|i| {
             if i < spent {
-                match race_diffs.get(i as usize).copied().unwrap_or(0) {
+                match race_diffs[i] {
                     d if d > 0 => r#"<span class="pip spent win"></span>"#,
                     d if d < 0 => r#"<span class="pip spent loss"></span>"#,
                     _ => r#"<span class="pip spent"></span>"#,
@@ -457,18 +669,14 @@ async fn overlay(path: web::Path<String>) -> Result<impl Responder> {
         })
         .collect();
 
-    let races_label = match race_left {
-        0 => "FINAL".to_string(),
-        1 => "1 RACE LEFT".to_string(),
-        n => format!("{n} RACES LEFT"),
-    };
-
-    let html_response = format!(
+    format!(
         r##"<!DOCTYPE html>
 <html lang="en">
 {head}
 <body>
   <div class="bug">
+    <p class="badge {badge_class}">{badge}</p>
+    <p class="h2h">{h2h}</p>
     <div class="panel">
       <div class="main">
         <p class="tag tag-home"><span class="tag-span">{tag}</span></p>
@@ -484,73 +692,119 @@ async fn overlay(path: web::Path<String>) -> Result<impl Responder> {
         <p class="tag tag-enemy"><span class="tag-span">{enemy_tag}</span></p>
       </div>
       <div class="strip">
+        <p class="track">{last_track}</p>
         <div class="pips">{pips}</div>
         <p class="races">{races_label}</p>
       </div>
+      <div class="scorers">{scorers}</div>
     </div>
   </div>
 </body>
 </html>"##,
-        head = OVERLAY_HEAD,
-    );
+        head = OVERLAY_HEAD.replacen("/* palette */", &palette::css(), 1),
+    )
+}
+
+/// Make user supplied text safe to put in HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The label next to the pips, e.g. "3 RACES LEFT" or "TIEBREAK 2/4".
+fn races_label(data: &OverlayData) -> String {
+    match data.phase {
+        Phase::Final => "FINAL".to_string(),
+        Phase::Tiebreak => format!(
+            "TIEBREAK {}/{}",
+            data.tiebreak_played + 1,
+            data.tiebreak_races
+        ),
+        Phase::Regulation if data.race_left == 1 => "1 RACE LEFT".to_string(),
+        Phase::Regulation => format!("{} RACES LEFT", data.race_left),
+    }
+}
 
-    Ok(HttpResponse::Ok()
-        .content_type("text/html")
-        .body(html_response))
+/// The badge above the panel and its class: who clinched, or what the home
+/// team needs per race to win.
+fn badge(data: &OverlayData) -> (String, &'static str) {
+    match (data.phase, data.clinched, data.required_avg_diff) {
+        (Phase::Final, _, _) => (String::new(), ""),
+        (_, Clinched::Home, _) => ("CLINCHED".to_string(), "home"),
+        (_, Clinched::Enemy, _) => ("CLINCHED".to_string(), "enemy"),
+        (_, Clinched::None, Some(needed)) if needed > 0.0 => (format!("NEEDS +{needed}/RACE"), ""),
+        (_, Clinched::None, _) => (String::new(), ""),
+    }
+}
+
+/// "H2H 5–3", with draws appended if there were any; empty before the
+/// first meeting.
+fn h2h_label(record: &stats::Record) -> String {
+    match record {
+        stats::Record { wars: 0, .. } => String::new(),
+        stats::Record { draws: 0, .. } => format!("H2H {}–{}", record.wins, record.losses),
+        _ => format!("H2H {}–{}–{}", record.wins, record.losses, record.draws),
+    }
 }
 
 #[get("/api/{channel_id}")]
-async fn index(path: web::Path<String>) -> Result<impl Responder> {
+async fn index(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
     let channel_id = path.into_inner();
 
-    Ok(web::Json(query_db(channel_id)))
-}
+    let war = store.get(&channel_id).await?;
+    archive::keep(store.get_ref(), &channel_id, &war).await;
 
-/// Run `query_db` on a blocking threadpool so the synchronous redis call
-/// never blocks the async runtime.
-async fn query(channel_id: &str) -> Option<OverlayData> {
-    let channel_id = channel_id.to_owned();
-    web::block(move || query_db(channel_id))
-        .await
-        .ok()
-        .flatten()
+    Ok(web::Json(validate::overlay(war, config.validation)?))
 }
 
-const DATA_UNAVAILABLE: &str = r#"{"error": "War data not available"}"#;
+fn snapshot_message(snapshot: &Snapshot) -> String {
+    match snapshot {
+        Ok(data) => serde_json::to_string(data).unwrap(),
+        Err(e) => e.body().to_string(),
+    }
+}
 
 #[get("/ws/{channel_id}")]
 async fn ws_index(
     req: HttpRequest,
     stream: web::Payload,
+    hub: web::Data<Hub>,
+    config: web::Data<Config>,
     path: web::Path<String>,
 ) -> Result<HttpResponse> {
     let channel_id = path.into_inner();
+    let heartbeat = config.heartbeat_interval();
+    let client_timeout = config.client_timeout();
     let (res, mut session, msg_stream) = actix_ws::handle(&req, stream)?;
     let mut msg_stream = msg_stream.aggregate_continuations();
+    let (initial, mut subscription) = hub.subscribe(&channel_id);
 
     rt::spawn(async move {
         let mut hb = Instant::now();
-        let mut last_data: Option<OverlayData> = None;
-
-        // Send initial state
-        match query(&channel_id).await {
-            Some(data) => {
-                if session
-                    .text(serde_json::to_string(&data).unwrap())
-                    .await
-                    .is_err()
-                {
-                    return;
-                }
-                last_data = Some(data);
-            }
-            None => {
-                let _ = session.text(DATA_UNAVAILABLE).await;
+
+        // Send initial state if the hub already has it, otherwise the first
+        // fetch arrives through the subscription.
+        if let Some(snapshot) = initial {
+            if session.text(snapshot_message(&snapshot)).await.is_err() {
+                return;
             }
         }
 
-        let mut hb_interval = interval(Duration::from_secs(30));
-        let mut poll_interval = interval(Duration::from_secs(1));
+        let mut hb_interval = interval(heartbeat);
 
         let close_reason = loop {
             tokio::select! {
@@ -566,22 +820,9 @@ async fn ws_index(
                             hb = Instant::now();
                         }
                         Some(Ok(AggregatedMessage::Text(_))) => {
-                            match query(&channel_id).await {
-                                Some(data) => {
-                                    if session
-                                        .text(serde_json::to_string(&data).unwrap())
-                                        .await
-                                        .is_err()
-                                    {
-                                        break None;
-                                    }
-                                    last_data = Some(data);
-                                }
-                                None => {
-                                    if session.text(DATA_UNAVAILABLE).await.is_err() {
-                                        break None;
-                                    }
-                                    last_data = None;
+                            if let Some(snapshot) = subscription.latest() {
+                                if session.text(snapshot_message(&snapshot)).await.is_err() {
+                                    break None;
                                 }
                             }
                         }
@@ -595,56 +836,21 @@ async fn ws_index(
                     }
                 }
                 _ = hb_interval.tick() => {
-                    if Instant::now().duration_since(hb) > Duration::from_secs(75) {
+                    if Instant::now().duration_since(hb) > client_timeout {
                         break None;
                     }
                     if session.ping(b"").await.is_err() {
                         break None;
                     }
                 }
-                _ = poll_interval.tick() => {
-                    let current_data = query(&channel_id).await;
-
-                    // Handle data availability changes
-                    match (&last_data, &current_data) {
-                        (Some(_), None) => {
-                            if session.text(DATA_UNAVAILABLE).await.is_err() {
-                                break None;
-                            }
-                            last_data = None;
-                        }
-                        (None, Some(new_data)) => {
-                            if session
-                                .text(serde_json::to_string(new_data).unwrap())
-                                .await
-                                .is_err()
-                            {
-                                break None;
-                            }
-                            last_data = current_data;
-                        }
-                        (Some(old_data), Some(new_data)) => {
-                            if old_data.tag != new_data.tag
-                                || old_data.enemy_tag != new_data.enemy_tag
-                                || old_data.score != new_data.score
-                                || old_data.enemy_score != new_data.enemy_score
-                                || old_data.diff != new_data.diff
-                                || old_data.race_left != new_data.race_left
-                                || old_data.race_diffs != new_data.race_diffs
-                                || old_data.home_pen != new_data.home_pen
-                                || old_data.enemy_pen != new_data.enemy_pen
-                            {
-                                if session
-                                    .text(serde_json::to_string(new_data).unwrap())
-                                    .await
-                                    .is_err()
-                                {
-                                    break None;
-                                }
-                            }
-                            last_data = current_data;
-                        }
-                        (None, None) => {}
+                snapshot = subscription.recv() => {
+                    // The hub only publishes changes, including the switch
+                    // between available and unavailable data.
+                    let Some(snapshot) = snapshot else {
+                        break None;
+                    };
+                    if session.text(snapshot_message(&snapshot)).await.is_err() {
+                        break None;
                     }
                 }
             }
@@ -656,10 +862,88 @@ async fn ws_index(
     Ok(res)
 }
 
+/// Write every war in the JSON object at `path` to the store, upgrading
+/// older schema versions on the way.
+async fn seed(store: &dyn WarStore, path: &Path) -> std::io::Result<()> {
+    let text = std::fs::read_to_string(path)?;
+    let wars: HashMap<String, serde_json::Value> =
+        serde_json::from_str(&text).map_err(std::io::Error::other)?;
+    for (channel_id, war) in wars {
+        let war = migrate::upgrade(war).map_err(std::io::Error::other)?.war;
+        store
+            .put(&channel_id, &war)
+            .await
+            .map_err(std::io::Error::other)?;
+    }
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    HttpServer::new(|| App::new().service(index).service(overlay).service(ws_index))
-        .bind("0.0.0.0:25991")?
-        .run()
-        .await
+    let config = match Config::load() {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            eprintln!("invalid configuration: {e}");
+            std::process::exit(1);
+        }
+    };
+
+    let store: Arc<dyn WarStore> = match config.store {
+        Backend::Redis => Arc::new(RedisStore::new(config.clone()).map_err(std::io::Error::other)?),
+        Backend::Memory => Arc::new(MemoryStore::default()),
+    };
+    if let Some(path) = &config.seed_file {
+        if let Err(e) = seed(store.as_ref(), path).await {
+            eprintln!("cannot seed from {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+
+    let hub = web::Data::new(Hub::new(store.clone(), config.validation));
+    let store = web::Data::from(store);
+    let bind = config.bind;
+    let config = web::Data::from(config);
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(hub.clone())
+            .app_data(store.clone())
+            .app_data(config.clone())
+            .service(index)
+            .service(api::create_war)
+            .service(api::add_race)
+            .service(api::edit_race)
+            .service(api::delete_race)
+            .service(api::set_roster)
+            .service(api::set_penalties)
+            .service(api::add_penalty)
+            .service(api::remove_penalty)
+            .service(api::finish_war)
+            .service(api::list_revisions)
+            .service(api::undo)
+            .service(api::redo)
+            .service(api::restore_revision)
+            .service(archive::history)
+            .service(archive::archived_war)
+            .service(export::live)
+            .service(export::archived)
+            .service(api::import_table)
+            .service(table::live)
+            .service(table::archived)
+            .service(image::table_svg)
+            .service(image::table_png)
+            .service(api::rotate_token)
+            .service(stats::tracks)
+            .service(stats::team)
+            .service(stats::versus)
+            // Before `overlay`, whose channel id would swallow the extension.
+            .service(image::overlay_png)
+            .service(overlay)
+            .service(ws_index)
+            .service(replay::replay_overlay)
+            .service(replay::replay_ws)
+    })
+    .bind(bind)?
+    .run()
+    .await
 }
@@ -0,0 +1,327 @@
+use crate::archive::ArchivedWar;
+use crate::error::WarError;
+use crate::history::{History, Revision, Step};
+use crate::store::{Edit, WarStore, Watch};
+use crate::war::WarData;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+/// Keeps wars in process memory, for local demos and tests without redis.
+pub struct MemoryStore {
+    wars: Mutex<HashMap<String, WarData>>,
+    tokens: Mutex<HashMap<String, String>>,
+    /// Locked after `wars` when both are needed.
+    histories: Mutex<HashMap<String, History>>,
+    /// Per channel, most recently archived last.
+    archives: Mutex<HashMap<String, Vec<ArchivedWar>>>,
+    /// Channel ids of every write, filtered by each watcher.
+    writes: broadcast::Sender<String>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self {
+            wars: Mutex::default(),
+            tokens: Mutex::default(),
+            histories: Mutex::default(),
+            archives: Mutex::default(),
+            writes: broadcast::channel(64).0,
+        }
+    }
+}
+
+#[async_trait]
+impl WarStore for MemoryStore {
+    async fn get(&self, channel_id: &str) -> Result<WarData, WarError> {
+        match self.wars.lock().unwrap().get(channel_id) {
+            Some(war) => Ok(war.clone()),
+            None => Err(WarError::NotFound),
+        }
+    }
+
+    async fn put(&self, channel_id: &str, war: &WarData) -> Result<(), WarError> {
+        self.wars
+            .lock()
+            .unwrap()
+            .insert(channel_id.to_owned(), war.clone());
+        let _ = self.writes.send(channel_id.to_owned());
+        Ok(())
+    }
+
+    async fn wars(&self) -> Result<Vec<WarData>, WarError> {
+        Ok(self.wars.lock().unwrap().values().cloned().collect())
+    }
+
+    async fn update(
+        &self,
+        channel_id: &str,
+        action: &str,
+        edit: Edit<'_>,
+    ) -> Result<WarData, WarError> {
+        let war = {
+            let mut wars = self.wars.lock().unwrap();
+            let previous = wars.get(channel_id).cloned();
+            let war = edit(previous.clone())?;
+            self.histories
+                .lock()
+                .unwrap()
+                .entry(channel_id.to_owned())
+                .or_default()
+                .record(previous.as_ref(), Revision::new(action, war.clone()));
+            wars.insert(channel_id.to_owned(), war.clone());
+            war
+        };
+        let _ = self.writes.send(channel_id.to_owned());
+        Ok(war)
+    }
+
+    async fn history(&self, channel_id: &str) -> Result<History, WarError> {
+        let histories = self.histories.lock().unwrap();
+        Ok(histories.get(channel_id).cloned().unwrap_or_default())
+    }
+
+    async fn step(&self, channel_id: &str, step: Step) -> Result<WarData, WarError> {
+        let war = {
+            let mut wars = self.wars.lock().unwrap();
+            let mut histories = self.histories.lock().unwrap();
+            let history = histories.entry(channel_id.to_owned()).or_default();
+            let war = history.step(wars.get(channel_id), step)?.clone();
+            wars.insert(channel_id.to_owned(), war.clone());
+            war
+        };
+        let _ = self.writes.send(channel_id.to_owned());
+        Ok(war)
+    }
+
+    async fn archive(&self, war: &ArchivedWar) -> Result<(), WarError> {
+        let mut archives = self.archives.lock().unwrap();
+        let archive = archives.entry(war.channel_id.clone()).or_default();
+        archive.retain(|archived| archived.id != war.id);
+        archive.push(war.clone());
+        Ok(())
+    }
+
+    async fn archived(
+        &self,
+        channel_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<ArchivedWar>, usize), WarError> {
+        let archives = self.archives.lock().unwrap();
+        let archive = archives.get(channel_id).map_or(&[][..], Vec::as_slice);
+        let page = archive.iter().rev().skip(offset).take(limit).cloned();
+        Ok((page.collect(), archive.len()))
+    }
+
+    async fn archived_war(&self, channel_id: &str, id: &str) -> Result<ArchivedWar, WarError> {
+        let archives = self.archives.lock().unwrap();
+        archives
+            .get(channel_id)
+            .and_then(|archive| archive.iter().find(|archived| archived.id == id))
+            .cloned()
+            .ok_or(WarError::NotFound)
+    }
+
+    async fn archived_by_id(&self, id: &str) -> Result<ArchivedWar, WarError> {
+        let archives = self.archives.lock().unwrap();
+        archives
+            .values()
+            .flatten()
+            .filter(|archived| archived.id == id)
+            .max_by_key(|archived| archived.finished_at)
+            .cloned()
+            .ok_or(WarError::NotFound)
+    }
+
+    async fn archived_all(&self) -> Result<Vec<ArchivedWar>, WarError> {
+        let archives = self.archives.lock().unwrap();
+        Ok(archives.values().flatten().cloned().collect())
+    }
+
+    async fn archived_by_tag(&self, tag: &str) -> Result<Vec<ArchivedWar>, WarError> {
+        let archives = self.archives.lock().unwrap();
+        Ok(archives
+            .values()
+            .flatten()
+            .filter(|archived| archived.war.involves(tag))
+            .cloned()
+            .collect())
+    }
+
+    async fn token(&self, channel_id: &str) -> Result<Option<String>, WarError> {
+        Ok(self.tokens.lock().unwrap().get(channel_id).cloned())
+    }
+
+    async fn set_token(
+        &self,
+        channel_id: &str,
+        token: &str,
+        replace: bool,
+    ) -> Result<bool, WarError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        if !replace && tokens.contains_key(channel_id) {
+            return Ok(false);
+        }
+        tokens.insert(channel_id.to_owned(), token.to_owned());
+        Ok(true)
+    }
+
+    async fn release_token(&self, channel_id: &str, token: &str) -> Result<(), WarError> {
+        let mut tokens = self.tokens.lock().unwrap();
+        if tokens
+            .get(channel_id)
+            .is_some_and(|current| current == token)
+        {
+            tokens.remove(channel_id);
+        }
+        Ok(())
+    }
+
+    async fn watch(&self, channel_id: &str) -> Box<dyn Watch> {
+        Box::new(MemoryWatch {
+            rx: self.writes.subscribe(),
+            channel_id: channel_id.to_owned(),
+        })
+    }
+}
+
+struct MemoryWatch {
+    rx: broadcast::Receiver<String>,
+    channel_id: String,
+}
+
+#[async_trait]
+impl Watch for MemoryWatch {
+    async fn changed(&mut self) {
+        loop {
+            match self.rx.recv().await {
+                Ok(id) if id == self.channel_id => return,
+                Ok(_) => {}
+                // Missed writes may have touched this channel.
+                Err(broadcast::error::RecvError::Lagged(_)) => return,
+                Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::war;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[actix_web::test]
+    async fn get_and_put() {
+        let store = MemoryStore::default();
+        assert!(matches!(store.get("ch").await, Err(WarError::NotFound)));
+        store.put("ch", &war(1)).await.unwrap();
+        assert_eq!(store.get("ch").await.unwrap().race_count(), 1);
+        assert_eq!(store.wars().await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn updates_are_recorded_and_stepped() {
+        let store = MemoryStore::default();
+        store
+            .update("ch", "create war", &|_| Ok(war(0)))
+            .await
+            .unwrap();
+        store
+            .update("ch", "add race", &|war| {
+                let mut war = war.unwrap();
+                war.push_race(50.0, 32.0, None);
+                Ok(war)
+            })
+            .await
+            .unwrap();
+        assert_eq!(store.history("ch").await.unwrap().revisions.len(), 2);
+
+        let failed = store
+            .update("ch", "fail", &|_| Err(WarError::Conflict("no".to_string())))
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(store.get("ch").await.unwrap().race_count(), 1);
+
+        let undone = store.step("ch", Step::Undo).await.unwrap();
+        assert_eq!(undone.race_count(), 0);
+        assert_eq!(store.get("ch").await.unwrap().race_count(), 0);
+        assert_eq!(store.step("ch", Step::Redo).await.unwrap().race_count(), 1);
+    }
+
+    #[actix_web::test]
+    async fn undo_after_a_put_returns_to_before_it() {
+        let store = MemoryStore::default();
+        store
+            .update("ch", "create war", &|_| Ok(war(1)))
+            .await
+            .unwrap();
+        store.put("ch", &war(3)).await.unwrap();
+
+        assert_eq!(store.step("ch", Step::Undo).await.unwrap().race_count(), 1);
+        assert_eq!(store.step("ch", Step::Redo).await.unwrap().race_count(), 3);
+
+        store.put("ch", &war(5)).await.unwrap();
+        let added = store
+            .update("ch", "add race", &|war| {
+                let mut war = war.unwrap();
+                war.push_race(50.0, 32.0, None);
+                Ok(war)
+            })
+            .await
+            .unwrap();
+        assert_eq!(added.race_count(), 6);
+        assert_eq!(store.step("ch", Step::Undo).await.unwrap().race_count(), 5);
+        assert_eq!(store.step("ch", Step::Undo).await.unwrap().race_count(), 3);
+    }
+
+    #[actix_web::test]
+    async fn archives() {
+        let store = MemoryStore::default();
+        let finished = war(12);
+        let archived = ArchivedWar::of("ch", &finished, &History::default()).unwrap();
+        store.archive(&archived).await.unwrap();
+        // The same war again replaces its earlier snapshot.
+        store.archive(&archived).await.unwrap();
+
+        let (page, total) = store.archived("ch", 0, 10).await.unwrap();
+        assert_eq!((page.len(), total), (1, 1));
+        assert_eq!(
+            store.archived_war("ch", &archived.id).await.unwrap().id,
+            archived.id
+        );
+        assert_eq!(
+            store.archived_by_id(&archived.id).await.unwrap().channel_id,
+            "ch"
+        );
+        assert_eq!(store.archived_by_tag("xyz").await.unwrap().len(), 1);
+        assert!(store.archived_by_tag("DEF").await.unwrap().is_empty());
+        assert_eq!(store.archived_all().await.unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn tokens() {
+        let store = MemoryStore::default();
+        assert_eq!(store.token("ch").await.unwrap(), None);
+        assert!(store.set_token("ch", "a", false).await.unwrap());
+        assert!(!store.set_token("ch", "b", false).await.unwrap());
+        assert!(store.set_token("ch", "c", true).await.unwrap());
+        assert_eq!(store.token("ch").await.unwrap().as_deref(), Some("c"));
+    }
+
+    #[actix_web::test]
+    async fn watches_see_their_channel_only() {
+        let store = MemoryStore::default();
+        let mut watch = store.watch("ch").await;
+        store.put("other", &war(0)).await.unwrap();
+        let quiet = timeout(Duration::from_millis(20), watch.changed()).await;
+        assert!(quiet.is_err());
+
+        store.put("ch", &war(0)).await.unwrap();
+        let woken = timeout(Duration::from_millis(20), watch.changed()).await;
+        assert!(woken.is_ok());
+    }
+}
@@ -0,0 +1,224 @@
+use crate::error::WarError;
+use crate::format::WarFormat;
+use crate::war::WarData;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Schema version written by this build.
+pub const CURRENT_VERSION: u64 = 7;
+
+type Step = fn(&mut Map<String, Value>);
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: [Step; CURRENT_VERSION as usize] = [
+    v0_to_v1, v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6, v6_to_v7,
+];
+
+/// A stored war after upgrading.
+pub struct Decoded {
+    pub war: WarData,
+    /// The document was older than [`CURRENT_VERSION`] and should be
+    /// rewritten with [`encode`].
+    pub migrated: bool,
+}
+
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u64,
+    #[serde(flatten)]
+    war: &'a WarData,
+}
+
+/// Serialize `war` in the current stored format.
+pub fn encode(war: &WarData) -> String {
+    serde_json::to_string(&Versioned {
+        version: CURRENT_VERSION,
+        war,
+    })
+    .unwrap()
+}
+
+/// [`encode`], as a JSON value for embedding in other documents.
+pub fn to_value(war: &WarData) -> Value {
+    serde_json::to_value(Versioned {
+        version: CURRENT_VERSION,
+        war,
+    })
+    .unwrap()
+}
+
+/// Parse a stored war of any known version.
+pub fn decode(json: &str) -> Result<Decoded, WarError> {
+    let value: Value = serde_json::from_str(json).map_err(WarError::from_json)?;
+    upgrade(value)
+}
+
+/// Bring an already parsed document up to [`CURRENT_VERSION`].
+pub fn upgrade(value: Value) -> Result<Decoded, WarError> {
+    let Value::Object(mut doc) = value else {
+        return Err(WarError::Schema("expected a JSON object".to_string()));
+    };
+    let version = match doc.remove("version") {
+        None => 0,
+        Some(v) => v.as_u64().ok_or_else(|| {
+            WarError::Schema("version must be a non-negative integer".to_string())
+        })?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(WarError::Schema(format!(
+            "version {version} is newer than the supported {CURRENT_VERSION}"
+        )));
+    }
+
+    for step in &MIGRATIONS[version as usize..] {
+        step(&mut doc);
+    }
+    let war = serde_json::from_value(Value::Object(doc)).map_err(WarError::from_json)?;
+
+    Ok(Decoded {
+        war,
+        migrated: version < CURRENT_VERSION,
+    })
+}
+
+/// Version 0 is whatever the bot wrote before documents were versioned:
+/// penalties and `last_diff` may be missing.
+fn v0_to_v1(doc: &mut Map<String, Value>) {
+    doc.entry("home_pen").or_insert(json!(0));
+    doc.entry("enemy_pen").or_insert(json!(0));
+    if !doc.contains_key("last_diff") {
+        let last = doc
+            .get("diff")
+            .and_then(Value::as_array)
+            .and_then(|diff| diff.last())
+            .cloned()
+            .unwrap_or(Value::Null);
+        doc.insert("last_diff".to_string(), last);
+    }
+}
+
+/// Version 2 records whether the war was ended through the API.
+fn v1_to_v2(doc: &mut Map<String, Value>) {
+    doc.insert("finished".to_string(), json!(false));
+}
+
+/// Version 3 stores the war's format; everything before was a 12 race 6v6.
+fn v2_to_v3(doc: &mut Map<String, Value>) {
+    doc.insert(
+        "format".to_string(),
+        serde_json::to_value(WarFormat::default()).unwrap(),
+    );
+}
+
+/// Version 4 records the course of each race; older races have none.
+fn v3_to_v4(doc: &mut Map<String, Value>) {
+    let races = doc
+        .get("diff")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    doc.insert("tracks".to_string(), json!(vec![Value::Null; races]));
+}
+
+/// Version 5 can carry a roster with per-player points.
+fn v4_to_v5(doc: &mut Map<String, Value>) {
+    doc.insert("roster".to_string(), json!([]));
+}
+
+/// Version 6 keeps a penalty log instead of one total per team. Old totals
+/// become a single entry each, with no reason or time.
+fn v5_to_v6(doc: &mut Map<String, Value>) {
+    let races = doc
+        .get("diff")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    let mut penalties = Vec::new();
+    for (key, team) in [("home_pen", "home"), ("enemy_pen", "enemy")] {
+        let amount = doc.remove(key).and_then(|v| v.as_i64()).unwrap_or(0);
+        if amount != 0 {
+            penalties.push(json!({
+                "team": team,
+                "amount": amount,
+                "race": races,
+                "reason": null,
+                "at": null,
+            }));
+        }
+    }
+    doc.insert("penalties".to_string(), Value::Array(penalties));
+}
+
+/// Version 7 records when the war started, which older wars don't know.
+fn v6_to_v7(doc: &mut Map<String, Value>) {
+    doc.insert("started_at".to_string(), Value::Null);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::war::Team;
+
+    /// What the bot wrote before documents were versioned.
+    fn v0() -> Value {
+        json!({
+            "tag": "ABC",
+            "enemy_tag": "XYZ",
+            "home_score": [50.0, 41.0],
+            "enemy_score": [32.0, 41.0],
+            "diff": [18, 0],
+            "home_pen": 10,
+        })
+    }
+
+    #[test]
+    fn upgrades_v0_through_every_step() {
+        let decoded = upgrade(v0()).unwrap();
+        assert!(decoded.migrated);
+        let war = decoded.war;
+        assert_eq!(war.last_diff, Some(0));
+        assert!(!war.finished);
+        assert_eq!(war.format, WarFormat::default());
+        assert_eq!(war.tracks, vec![None, None]);
+        assert!(war.roster.is_empty());
+        assert_eq!(war.penalties.len(), 1);
+        assert_eq!(war.penalties[0].team, Team::Home);
+        assert_eq!(war.penalties[0].amount, 10);
+        assert_eq!(war.penalties[0].race, 2);
+        assert_eq!(war.penalty(Team::Enemy), 0);
+        assert_eq!(war.started_at, None);
+    }
+
+    #[test]
+    fn upgrades_from_each_version() {
+        // Apply the first `version` steps by hand, then let `upgrade` finish.
+        for version in 0..=CURRENT_VERSION {
+            let Value::Object(mut doc) = v0() else {
+                unreachable!()
+            };
+            for step in &MIGRATIONS[..version as usize] {
+                step(&mut doc);
+            }
+            doc.insert("version".to_string(), json!(version));
+            let decoded = upgrade(Value::Object(doc)).unwrap();
+            assert_eq!(decoded.migrated, version < CURRENT_VERSION, "v{version}");
+            assert_eq!(decoded.war.totals(), (81, 73), "v{version}");
+        }
+    }
+
+    #[test]
+    fn current_documents_round_trip() {
+        let war = upgrade(v0()).unwrap().war;
+        let decoded = decode(&encode(&war)).unwrap();
+        assert!(!decoded.migrated);
+        assert_eq!(decoded.war.diff, war.diff);
+        assert_eq!(decoded.war.penalties, war.penalties);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let mut doc = v0();
+        doc["version"] = json!(CURRENT_VERSION + 1);
+        assert!(matches!(upgrade(doc), Err(WarError::Schema(_))));
+        assert!(matches!(upgrade(json!([])), Err(WarError::Schema(_))));
+        assert!(decode("{").is_err());
+    }
+}
@@ -0,0 +1,35 @@
+/// Panel background.
+pub const GLASS: &str = "rgba(13, 16, 23, 0.62)";
+pub const STROKE: &str = "rgba(247, 248, 244, 0.16)";
+pub const CHALK: &str = "#F7F8F4";
+pub const CHALK_DIM: &str = "rgba(247, 248, 244, 0.6)";
+/// The home team.
+pub const LEAD: &str = "#FFC530";
+/// The enemy team.
+pub const TRAIL: &str = "#5BC2FF";
+pub const PEN: &str = "#FF5A5F";
+pub const WIN: &str = "#4ADE80";
+pub const LOSS: &str = "#FF5A5F";
+pub const INK: &str = "#10131A";
+
+/// The palette as the overlay's CSS custom properties, e.g. `--lead`.
+pub fn css() -> String {
+    let vars = [
+        ("glass", GLASS),
+        ("stroke", STROKE),
+        ("chalk", CHALK),
+        ("chalk-dim", CHALK_DIM),
+        ("lead", LEAD),
+        ("trail", TRAIL),
+        ("pen", PEN),
+        ("win", WIN),
+        ("loss", LOSS),
+        ("ink", INK),
+    ];
+    let mut css = String::from(":root {\n");
+    for (name, value) in vars {
+        css.push_str(&format!("  --{name}: {value};\n"));
+    }
+    css.push('}');
+    css
+}
@@ -0,0 +1,602 @@
+use crate::archive::ArchivedWar;
+use crate::config::Config;
+use crate::error::WarError;
+use crate::history::{History, Revision, Step, BEFORE_HISTORY, EXTERNAL_WRITE, MAX_REVISIONS};
+use crate::migrate;
+use crate::store::{Edit, WarStore, Watch};
+use crate::updates::Updates;
+use crate::war::WarData;
+use async_trait::async_trait;
+use log::{error, info, warn};
+use redis::aio::{ConnectionManager, ConnectionManagerConfig, MultiplexedConnection};
+use redis::AsyncCommands;
+use std::sync::Arc;
+
+/// How often `update` retries when the war is written concurrently.
+const UPDATE_ATTEMPTS: usize = 5;
+
+/// Everything that isn't a war lives under this prefix.
+const KEY_PREFIX: &str = "war_score:";
+
+/// Channel tokens live under this prefix + channel id.
+const TOKEN_PREFIX: &str = "war_score:token:";
+
+/// Revisions of a channel's war, a list of JSON documents, oldest first.
+const HISTORY_PREFIX: &str = "war_score:revisions:";
+
+/// Index of the live revision in the channel's history list.
+const CURSOR_PREFIX: &str = "war_score:revision:";
+
+/// Archived wars of a channel, a hash of id to JSON document.
+const ARCHIVE_PREFIX: &str = "war_score:archive:";
+
+/// Archived war ids of a channel, a sorted set scored by archive time.
+const ARCHIVE_ORDER_PREFIX: &str = "war_score:archive_order:";
+
+/// Archived wars a tag played in, a set of `{id}:{channel_id}`. The tag is
+/// lowercased.
+const ARCHIVE_TAG_PREFIX: &str = "war_score:archive_tag:";
+
+/// Every archived war, a set of `{id}:{channel_id}`.
+const ARCHIVE_ALL: &str = "war_score:archive_all";
+
+/// Channel of each archived war, a hash of id to channel id.
+const ARCHIVE_IDS: &str = "war_score:archive_ids";
+
+/// Keys asked for per SCAN and MGET round trip when listing wars.
+const SCAN_BATCH: usize = 200;
+
+/// What a transaction does to a channel's war.
+enum Change<'a> {
+    Update { action: &'a str, edit: Edit<'a> },
+    Step(Step),
+}
+
+/// The keys holding a channel's state.
+struct Keys<'a> {
+    war: &'a str,
+    history: String,
+    cursor: String,
+}
+
+impl<'a> Keys<'a> {
+    fn new(channel_id: &'a str) -> Result<Self, WarError> {
+        Ok(Self {
+            war: war_key(channel_id)?,
+            history: format!("{HISTORY_PREFIX}{channel_id}"),
+            cursor: format!("{CURSOR_PREFIX}{channel_id}"),
+        })
+    }
+}
+
+/// The key of the channel's war. Channel ids under [`KEY_PREFIX`] would
+/// read or overwrite our own keys, tokens included.
+fn war_key(channel_id: &str) -> Result<&str, WarError> {
+    if channel_id.starts_with(KEY_PREFIX) {
+        return Err(WarError::BadRequest(format!(
+            "channel ids must not start with {KEY_PREFIX}"
+        )));
+    }
+    Ok(channel_id)
+}
+
+/// Wars stored as JSON strings under the bare channel id.
+pub struct RedisStore {
+    client: redis::Client,
+    /// Cheap to clone; clones multiplex onto the same connection, which is
+    /// re-established in the background when redis goes away.
+    con: ConnectionManager,
+    config: Arc<Config>,
+}
+
+impl RedisStore {
+    pub fn new(config: Arc<Config>) -> redis::RedisResult<Self> {
+        let client = redis::Client::open(config.redis_url.as_str())?;
+        // Lazy so the server still comes up while redis is down; the manager
+        // connects on first use and reconnects on its own afterwards.
+        let con = client.get_connection_manager_lazy(ConnectionManagerConfig::new())?;
+        Ok(Self {
+            client,
+            con,
+            config,
+        })
+    }
+}
+
+impl RedisStore {
+    /// The archived wars listed in `set`, whose members are
+    /// `{id}:{channel_id}`.
+    async fn archived_in(&self, set: &str) -> Result<Vec<ArchivedWar>, WarError> {
+        let mut con = self.con.clone();
+        let members: Vec<String> = con.smembers(set).await.map_err(unavailable)?;
+        if members.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut pipe = redis::pipe();
+        for member in &members {
+            if let Some((id, channel_id)) = member.split_once(':') {
+                pipe.hget(format!("{ARCHIVE_PREFIX}{channel_id}"), id);
+            }
+        }
+        let docs: Vec<Option<String>> = pipe.query_async(&mut con).await.map_err(unavailable)?;
+        docs.iter()
+            .flatten()
+            .map(|json| ArchivedWar::decode(json))
+            .collect()
+    }
+
+    /// SET the war and publish the change so sessions in `pubsub` mode see
+    /// our own writes. Replies with the PUBLISH receiver count.
+    fn write_pipe(&self, channel_id: &str, war: &WarData) -> redis::Pipeline {
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .set(channel_id, migrate::encode(war))
+            .ignore()
+            .publish(
+                format!("{}{channel_id}", self.config.update_channel_prefix),
+                "set",
+            );
+        pipe
+    }
+
+    /// Apply `change` under WATCH until its transaction commits.
+    async fn transact(&self, channel_id: &str, change: Change<'_>) -> Result<WarData, WarError> {
+        let keys = Keys::new(channel_id)?;
+        // WATCH is per connection, so transactions can't share the manager.
+        let mut con = self
+            .client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(unavailable)?;
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            redis::cmd("WATCH")
+                .arg(keys.war)
+                .arg(&keys.history)
+                .arg(&keys.cursor)
+                .exec_async(&mut con)
+                .await
+                .map_err(unavailable)?;
+            let prepared = match &change {
+                Change::Update { action, edit } => {
+                    self.prepare_update(&mut con, &keys, action, *edit).await
+                }
+                Change::Step(step) => self.prepare_step(&mut con, &keys, *step).await,
+            };
+            let (war, pipe) = match prepared {
+                Ok(v) => v,
+                Err(e) => {
+                    let _ = redis::cmd("UNWATCH").exec_async(&mut con).await;
+                    return Err(e);
+                }
+            };
+
+            // EXEC replies nil when a watched key changed since WATCH.
+            let committed: Option<(i64,)> =
+                pipe.query_async(&mut con).await.map_err(unavailable)?;
+            if committed.is_some() {
+                return Ok(war);
+            }
+        }
+
+        Err(WarError::Conflict(
+            "war kept changing while updating it, try again".to_string(),
+        ))
+    }
+
+    /// Read the war, apply `edit` and build the transaction writing it as a
+    /// new revision; the same steps as `History::record`, on the list.
+    async fn prepare_update(
+        &self,
+        con: &mut MultiplexedConnection,
+        keys: &Keys<'_>,
+        action: &str,
+        edit: Edit<'_>,
+    ) -> Result<(WarData, redis::Pipeline), WarError> {
+        let current: Option<String> = con.get(keys.war).await.map_err(unavailable)?;
+        let cursor: Option<usize> = con.get(&keys.cursor).await.map_err(unavailable)?;
+        let previous = current
+            .map(|json| migrate::decode(&json).map(|decoded| decoded.war))
+            .transpose()?;
+        let war = edit(previous.clone())?;
+
+        let mut pipe = self.write_pipe(keys.war, &war);
+        let kept = match (cursor, previous) {
+            (Some(cursor), previous) => {
+                pipe.ltrim(&keys.history, 0, cursor as isize).ignore();
+                match self.external(con, keys, cursor, previous).await? {
+                    Some(previous) => {
+                        let external = Revision::new(EXTERNAL_WRITE, previous);
+                        pipe.rpush(&keys.history, external.encode()).ignore();
+                        cursor + 2
+                    }
+                    None => cursor + 1,
+                }
+            }
+            (None, Some(previous)) => {
+                let before = Revision::new(BEFORE_HISTORY, previous);
+                pipe.del(&keys.history)
+                    .ignore()
+                    .rpush(&keys.history, before.encode())
+                    .ignore();
+                1
+            }
+            (None, None) => {
+                pipe.del(&keys.history).ignore();
+                0
+            }
+        };
+        let len = (kept + 1).min(MAX_REVISIONS);
+        pipe.rpush(&keys.history, Revision::new(action, war.clone()).encode())
+            .ignore()
+            .ltrim(&keys.history, -(MAX_REVISIONS as isize), -1)
+            .ignore()
+            .set(&keys.cursor, len - 1)
+            .ignore();
+        Ok((war, pipe))
+    }
+
+    /// Find the revision `step` leads to and build the transaction making it
+    /// live.
+    async fn prepare_step(
+        &self,
+        con: &mut MultiplexedConnection,
+        keys: &Keys<'_>,
+        step: Step,
+    ) -> Result<(WarData, redis::Pipeline), WarError> {
+        let current: Option<String> = con.get(keys.war).await.map_err(unavailable)?;
+        let cursor: Option<usize> = con.get(&keys.cursor).await.map_err(unavailable)?;
+        let len: usize = con.llen(&keys.history).await.map_err(unavailable)?;
+        let live = current
+            .map(|json| migrate::decode(&json).map(|decoded| decoded.war))
+            .transpose()?;
+        let external = match cursor {
+            Some(cursor) => self.external(con, keys, cursor, live).await?,
+            None => None,
+        };
+        // A live war written around the history is recorded first, as
+        // `History::step` does. That leaves only undo, back to `cursor`,
+        // which the list holds already.
+        let (target, dropped) = match (cursor, &external) {
+            (Some(cursor), Some(_)) => (
+                step.target(cursor + 1, cursor + 2)?,
+                (cursor + 2).saturating_sub(MAX_REVISIONS),
+            ),
+            (Some(cursor), None) => (step.target(cursor, len)?, 0),
+            (None, _) => (step.target(0, 0)?, 0),
+        };
+        let json: Option<String> = con
+            .lindex(&keys.history, target as isize)
+            .await
+            .map_err(unavailable)?;
+        let json = json.ok_or_else(|| {
+            WarError::Conflict("history changed while moving through it".to_string())
+        })?;
+        let war = Revision::decode(&json)?.war;
+
+        let mut pipe = self.write_pipe(keys.war, &war);
+        if let Some(live) = external {
+            pipe.ltrim(&keys.history, 0, target as isize)
+                .ignore()
+                .rpush(&keys.history, Revision::new(EXTERNAL_WRITE, live).encode())
+                .ignore()
+                .ltrim(&keys.history, -(MAX_REVISIONS as isize), -1)
+                .ignore();
+        }
+        pipe.set(&keys.cursor, target - dropped).ignore();
+        Ok((war, pipe))
+    }
+
+    /// `live` if it differs from the war of revision `cursor`, i.e. it was
+    /// written around the history.
+    async fn external(
+        &self,
+        con: &mut MultiplexedConnection,
+        keys: &Keys<'_>,
+        cursor: usize,
+        live: Option<WarData>,
+    ) -> Result<Option<WarData>, WarError> {
+        let Some(live) = live else {
+            return Ok(None);
+        };
+        let json: Option<String> = con
+            .lindex(&keys.history, cursor as isize)
+            .await
+            .map_err(unavailable)?;
+        let Some(json) = json else {
+            return Ok(None);
+        };
+        let recorded = Revision::decode(&json)?.war;
+        Ok((live != recorded).then_some(live))
+    }
+
+    /// Replace `old` with the current encoding of `war`, unless someone
+    /// wrote the key in the meantime.
+    async fn rewrite(&self, channel_id: &str, old: &str, war: &WarData) {
+        let script = redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('SET', KEYS[1], ARGV[2])
+            end
+            return false",
+        );
+        let mut con = self.con.clone();
+        let res: redis::RedisResult<Option<String>> = script
+            .key(channel_id)
+            .arg(old)
+            .arg(migrate::encode(war))
+            .invoke_async(&mut con)
+            .await;
+        match res {
+            Ok(Some(_)) => {
+                info!(target: channel_id, "rewrote war in schema v{}", migrate::CURRENT_VERSION)
+            }
+            Ok(None) => {}
+            Err(e) => warn!(target: channel_id, "cannot rewrite migrated war: {e}"),
+        }
+    }
+}
+
+#[async_trait]
+impl WarStore for RedisStore {
+    async fn get(&self, channel_id: &str) -> Result<WarData, WarError> {
+        let mut con = self.con.clone();
+        let war_data: String = match con.get(war_key(channel_id)?).await {
+            Ok(Some(v)) => v,
+            Ok(None) => return Err(WarError::NotFound),
+            Err(e) => {
+                error!(target: channel_id, "{e}");
+                return Err(WarError::Unavailable(e.to_string()));
+            }
+        };
+        info!(target: channel_id, "war data: {} bytes", war_data.len());
+
+        let decoded = match migrate::decode(war_data.as_str()) {
+            Ok(v) => v,
+            Err(e) => {
+                error!(target: channel_id, "{e}");
+                return Err(e);
+            }
+        };
+        info!(target: channel_id, "data parsed");
+
+        if decoded.migrated && self.config.rewrite_migrated {
+            self.rewrite(channel_id, &war_data, &decoded.war).await;
+        }
+
+        Ok(decoded.war)
+    }
+
+    async fn put(&self, channel_id: &str, war: &WarData) -> Result<(), WarError> {
+        let mut con = self.con.clone();
+        self.write_pipe(war_key(channel_id)?, war)
+            .query_async::<()>(&mut con)
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn wars(&self) -> Result<Vec<WarData>, WarError> {
+        let mut con = self.con.clone();
+        let mut wars = Vec::new();
+        let mut cursor = 0u64;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .arg("TYPE")
+                .arg("string")
+                .query_async(&mut con)
+                .await
+                .map_err(unavailable)?;
+            let keys: Vec<String> = keys
+                .into_iter()
+                .filter(|key| !key.starts_with(KEY_PREFIX))
+                .collect();
+            if !keys.is_empty() {
+                let values: Vec<Option<String>> = redis::cmd("MGET")
+                    .arg(&keys)
+                    .query_async(&mut con)
+                    .await
+                    .map_err(unavailable)?;
+                // Other keys in the database simply don't decode.
+                wars.extend(
+                    values
+                        .iter()
+                        .flatten()
+                        .filter_map(|json| migrate::decode(json).ok())
+                        .map(|decoded| decoded.war),
+                );
+            }
+            if next == 0 {
+                return Ok(wars);
+            }
+            cursor = next;
+        }
+    }
+
+    async fn update(
+        &self,
+        channel_id: &str,
+        action: &str,
+        edit: Edit<'_>,
+    ) -> Result<WarData, WarError> {
+        self.transact(channel_id, Change::Update { action, edit })
+            .await
+    }
+
+    async fn history(&self, channel_id: &str) -> Result<History, WarError> {
+        let keys = Keys::new(channel_id)?;
+        let mut con = self.con.clone();
+        let (cursor, revisions): (Option<usize>, Vec<String>) = redis::pipe()
+            .atomic()
+            .get(&keys.cursor)
+            .lrange(&keys.history, 0, -1)
+            .query_async(&mut con)
+            .await
+            .map_err(unavailable)?;
+        let (Some(cursor), false) = (cursor, revisions.is_empty()) else {
+            return Ok(History::default());
+        };
+        let revisions = revisions
+            .iter()
+            .map(|json| Revision::decode(json))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(History {
+            cursor: cursor.min(revisions.len() - 1),
+            revisions,
+        })
+    }
+
+    async fn step(&self, channel_id: &str, step: Step) -> Result<WarData, WarError> {
+        self.transact(channel_id, Change::Step(step)).await
+    }
+
+    async fn archive(&self, war: &ArchivedWar) -> Result<(), WarError> {
+        let mut con = self.con.clone();
+        // Archive ids never contain ':', channel ids might.
+        let member = format!("{}:{}", war.id, war.channel_id);
+        redis::pipe()
+            .atomic()
+            .hset(
+                format!("{ARCHIVE_PREFIX}{}", war.channel_id),
+                &war.id,
+                war.encode(),
+            )
+            .ignore()
+            .zadd(
+                format!("{ARCHIVE_ORDER_PREFIX}{}", war.channel_id),
+                &war.id,
+                war.finished_at.unwrap_or(0),
+            )
+            .ignore()
+            .hset(ARCHIVE_IDS, &war.id, &war.channel_id)
+            .ignore()
+            .sadd(ARCHIVE_ALL, &member)
+            .ignore()
+            .sadd(tag_key(&war.war.tag), &member)
+            .ignore()
+            .sadd(tag_key(&war.war.enemy_tag), &member)
+            .ignore()
+            .query_async::<()>(&mut con)
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn archived(
+        &self,
+        channel_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<ArchivedWar>, usize), WarError> {
+        let mut con = self.con.clone();
+        let order = format!("{ARCHIVE_ORDER_PREFIX}{channel_id}");
+        let (ids, total): (Vec<String>, usize) = redis::pipe()
+            .atomic()
+            .zrevrange(&order, offset as isize, (offset + limit) as isize - 1)
+            .zcard(&order)
+            .query_async(&mut con)
+            .await
+            .map_err(unavailable)?;
+        if ids.is_empty() {
+            return Ok((Vec::new(), total));
+        }
+
+        let docs: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(format!("{ARCHIVE_PREFIX}{channel_id}"))
+            .arg(&ids)
+            .query_async(&mut con)
+            .await
+            .map_err(unavailable)?;
+        let wars = docs
+            .iter()
+            .flatten()
+            .map(|json| ArchivedWar::decode(json))
+            .collect::<Result<_, _>>()?;
+        Ok((wars, total))
+    }
+
+    async fn archived_war(&self, channel_id: &str, id: &str) -> Result<ArchivedWar, WarError> {
+        let mut con = self.con.clone();
+        let json: Option<String> = con
+            .hget(format!("{ARCHIVE_PREFIX}{channel_id}"), id)
+            .await
+            .map_err(unavailable)?;
+        ArchivedWar::decode(&json.ok_or(WarError::NotFound)?)
+    }
+
+    async fn archived_by_id(&self, id: &str) -> Result<ArchivedWar, WarError> {
+        let mut con = self.con.clone();
+        let channel_id: Option<String> = con.hget(ARCHIVE_IDS, id).await.map_err(unavailable)?;
+        self.archived_war(&channel_id.ok_or(WarError::NotFound)?, id)
+            .await
+    }
+
+    async fn archived_all(&self) -> Result<Vec<ArchivedWar>, WarError> {
+        self.archived_in(ARCHIVE_ALL).await
+    }
+
+    async fn archived_by_tag(&self, tag: &str) -> Result<Vec<ArchivedWar>, WarError> {
+        self.archived_in(&tag_key(tag)).await
+    }
+
+    async fn token(&self, channel_id: &str) -> Result<Option<String>, WarError> {
+        let mut con = self.con.clone();
+        con.get(format!("{TOKEN_PREFIX}{channel_id}"))
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn set_token(
+        &self,
+        channel_id: &str,
+        token: &str,
+        replace: bool,
+    ) -> Result<bool, WarError> {
+        let mut con = self.con.clone();
+        let mut cmd = redis::cmd("SET");
+        cmd.arg(format!("{TOKEN_PREFIX}{channel_id}")).arg(token);
+        if !replace {
+            cmd.arg("NX");
+        }
+        // SET NX replies nil when the key already exists.
+        let stored: Option<String> = cmd.query_async(&mut con).await.map_err(unavailable)?;
+        Ok(stored.is_some())
+    }
+
+    async fn release_token(&self, channel_id: &str, token: &str) -> Result<(), WarError> {
+        let script = redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0",
+        );
+        let mut con = self.con.clone();
+        script
+            .key(format!("{TOKEN_PREFIX}{channel_id}"))
+            .arg(token)
+            .invoke_async::<()>(&mut con)
+            .await
+            .map_err(unavailable)
+    }
+
+    async fn watch(&self, channel_id: &str) -> Box<dyn Watch> {
+        Box::new(
+            Updates::new(
+                self.client.clone(),
+                self.con.clone(),
+                &self.config,
+                channel_id,
+            )
+            .await,
+        )
+    }
+}
+
+fn unavailable(e: redis::RedisError) -> WarError {
+    WarError::Unavailable(e.to_string())
+}
+
+fn tag_key(tag: &str) -> String {
+    format!("{ARCHIVE_TAG_PREFIX}{}", tag.to_ascii_lowercase())
+}
@@ -0,0 +1,311 @@
+use crate::config::Config;
+use crate::error::WarError;
+use crate::hub::Snapshot;
+use crate::store::WarStore;
+use crate::validate;
+use crate::war::{OverlayData, WarData};
+use crate::{overlay_page, snapshot_message, OVERLAY_SOCKET};
+use actix_web::{get, rt, web, HttpRequest, HttpResponse, Responder, Result};
+use actix_ws::AggregatedMessage;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::time::{interval, sleep_until};
+
+/// Seconds between races unless asked otherwise.
+const DEFAULT_DELAY: f64 = 8.0;
+const MAX_DELAY: f64 = 3_600.0;
+const MIN_SPEED: f64 = 0.01;
+const MAX_SPEED: f64 = 100.0;
+
+/// How long to wait between races.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Timing {
+    /// `delay` seconds each.
+    #[default]
+    Fixed,
+    /// As long as the war took, where it was recorded; `delay` elsewhere.
+    Original,
+}
+
+#[derive(Deserialize)]
+pub struct ReplayQuery {
+    /// Playback rate: 2 waits half as long.
+    speed: Option<f64>,
+    /// Seconds between races.
+    delay: Option<f64>,
+    #[serde(default)]
+    timing: Timing,
+}
+
+impl ReplayQuery {
+    fn check(&self) -> Result<(), WarError> {
+        let speed = self.speed.unwrap_or(1.0);
+        if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+            return Err(WarError::BadRequest(format!(
+                "speed must be between {MIN_SPEED} and {MAX_SPEED}"
+            )));
+        }
+        let delay = self.delay.unwrap_or(DEFAULT_DELAY);
+        if !(0.0..=MAX_DELAY).contains(&delay) {
+            return Err(WarError::BadRequest(format!(
+                "delay must be between 0 and {MAX_DELAY} seconds"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Wait before showing race `race` (1-based), given when each race was
+    /// entered. Recorded gaps are capped like `delay`, so a war left open
+    /// overnight doesn't stall its replay.
+    fn wait(&self, race: usize, times: &[Option<u64>]) -> Duration {
+        let delay = self.delay.unwrap_or(DEFAULT_DELAY);
+        let recorded = match (race.checked_sub(2), race.checked_sub(1)) {
+            (Some(before), Some(this)) if self.timing == Timing::Original => {
+                match (
+                    times.get(before).copied().flatten(),
+                    times.get(this).copied().flatten(),
+                ) {
+                    (Some(before), Some(this)) => {
+                        Some((this.saturating_sub(before) as f64).min(MAX_DELAY))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Duration::from_secs_f64(recorded.unwrap_or(delay) / self.speed.unwrap_or(1.0))
+    }
+}
+
+/// `war` as it stood after its first `races` races.
+fn after(war: &WarData, races: usize) -> WarData {
+    let mut war = war.clone();
+    war.finished &= races == war.race_count();
+    war.home_score.truncate(races);
+    war.enemy_score.truncate(races);
+    war.diff.truncate(races);
+    war.tracks.truncate(races);
+    for player in &mut war.roster {
+        player.points.truncate(races);
+    }
+    war.penalties
+        .retain(|penalty| penalty.race as usize <= races);
+    war.last_diff = war.diff.last().copied();
+    war
+}
+
+/// What overlays see during a replay: the war before its first race, then
+/// after each one, with the wait before it.
+fn frames(
+    war: &WarData,
+    times: &[Option<u64>],
+    query: &ReplayQuery,
+    config: &Config,
+) -> Vec<(Duration, Snapshot)> {
+    (0..=war.race_count())
+        .map(|races| {
+            let wait = if races == 0 {
+                Duration::ZERO
+            } else {
+                query.wait(races, times)
+            };
+            (
+                wait,
+                validate::overlay(after(war, races), config.validation),
+            )
+        })
+        .collect()
+}
+
+/// Play an archived war back race by race, in the messages the live
+/// websocket sends. The last frame stays up until the overlay disconnects.
+#[get("/ws/replay/{war_id}")]
+async fn replay_ws(
+    req: HttpRequest,
+    stream: web::Payload,
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+    query: web::Query<ReplayQuery>,
+) -> Result<HttpResponse> {
+    query.check()?;
+    let archived = store.archived_by_id(&path.into_inner()).await?;
+    let frames = frames(&archived.war, &archived.race_times, &query, &config);
+
+    let heartbeat = config.heartbeat_interval();
+    let client_timeout = config.client_timeout();
+    let (res, mut session, msg_stream) = actix_ws::handle(&req, stream)?;
+    let mut msg_stream = msg_stream.aggregate_continuations();
+
+    rt::spawn(async move {
+        let mut hb = Instant::now();
+        let mut hb_interval = interval(heartbeat);
+        let mut frames = frames.into_iter();
+        let mut shown: Option<Snapshot> = None;
+        let mut next = frames.next();
+        // A deadline rather than a fresh sleep per loop, so pings don't push
+        // the next race back.
+        let mut due = tokio::time::Instant::now();
+
+        let close_reason = loop {
+            tokio::select! {
+                msg = msg_stream.next() => {
+                    match msg {
+                        Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                            hb = Instant::now();
+                            if session.pong(&bytes).await.is_err() {
+                                break None;
+                            }
+                        }
+                        Some(Ok(AggregatedMessage::Pong(_))) => {
+                            hb = Instant::now();
+                        }
+                        Some(Ok(AggregatedMessage::Text(_))) => {
+                            if let Some(snapshot) = &shown {
+                                if session.text(snapshot_message(snapshot)).await.is_err() {
+                                    break None;
+                                }
+                            }
+                        }
+                        Some(Ok(AggregatedMessage::Binary(bin))) => {
+                            if session.binary(bin).await.is_err() {
+                                break None;
+                            }
+                        }
+                        Some(Ok(AggregatedMessage::Close(reason))) => break reason,
+                        Some(Err(_)) | None => break None,
+                    }
+                }
+                _ = hb_interval.tick() => {
+                    if Instant::now().duration_since(hb) > client_timeout {
+                        break None;
+                    }
+                    if session.ping(b"").await.is_err() {
+                        break None;
+                    }
+                }
+                _ = sleep_until(due), if next.is_some() => {
+                    let Some((_, snapshot)) = next.take() else {
+                        continue;
+                    };
+                    if session.text(snapshot_message(&snapshot)).await.is_err() {
+                        break None;
+                    }
+                    shown = Some(snapshot);
+                    next = frames.next();
+                    if let Some((wait, _)) = &next {
+                        due = tokio::time::Instant::now() + *wait;
+                    }
+                }
+            }
+        };
+
+        let _ = session.close(close_reason).await;
+    });
+
+    Ok(res)
+}
+
+/// The overlay page, playing an archived war back: the live page with its
+/// websocket pointed at [`replay_ws`]. Takes the same query, which it passes
+/// on.
+#[get("/overlay/replay/{war_id}")]
+async fn replay_overlay(
+    store: web::Data<dyn WarStore>,
+    config: web::Data<Config>,
+    path: web::Path<String>,
+) -> Result<impl Responder> {
+    let archived = store.archived_by_id(&path.into_inner()).await?;
+    let first = validate::overlay(after(&archived.war, 0), config.validation).ok();
+    Ok(HttpResponse::Ok()
+        .content_type("text/html")
+        .body(replay_page(first.as_ref())))
+}
+
+fn replay_page(first: Option<&OverlayData>) -> String {
+    overlay_page(first, "").replacen(
+        OVERLAY_SOCKET,
+        "'/ws/replay/' + channel + window.location.search",
+        1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::war;
+    use crate::war::{Penalty, Team};
+
+    fn query(speed: Option<f64>, delay: Option<f64>, timing: Timing) -> ReplayQuery {
+        ReplayQuery {
+            speed,
+            delay,
+            timing,
+        }
+    }
+
+    #[test]
+    fn frames_show_the_war_race_by_race() {
+        let mut war = war(3);
+        war.finished = true;
+        war.penalties.push(Penalty::now(Team::Home, 10, 0, None));
+        war.penalties.push(Penalty::now(Team::Enemy, 5, 2, None));
+
+        let start = after(&war, 0);
+        assert_eq!(start.race_count(), 0);
+        assert_eq!(start.last_diff, None);
+        assert!(!start.finished);
+
+        let first = after(&war, 1);
+        assert_eq!(first.race_count(), 1);
+        assert_eq!(first.penalties.len(), 1);
+        assert_eq!(first.last_diff, Some(18));
+        assert!(!first.finished);
+
+        assert!(after(&war, 3).finished);
+        assert_eq!(after(&war, 3).penalties.len(), 2);
+    }
+
+    #[test]
+    fn waits_are_fixed_by_default() {
+        let fixed = query(None, None, Timing::Fixed);
+        assert_eq!(fixed.wait(1, &[]), Duration::from_secs(8));
+        let fast = query(Some(2.0), Some(3.0), Timing::Fixed);
+        assert_eq!(
+            fast.wait(2, &[Some(0), Some(60)]),
+            Duration::from_millis(1_500)
+        );
+    }
+
+    #[test]
+    fn original_timing_follows_the_recorded_gaps() {
+        let times = [Some(100), Some(130), None, Some(200), Some(10_000)];
+        let original = query(Some(2.0), Some(4.0), Timing::Original);
+        assert_eq!(original.wait(1, &times), Duration::from_secs(2));
+        assert_eq!(original.wait(2, &times), Duration::from_secs(15));
+        assert_eq!(original.wait(3, &times), Duration::from_secs(2));
+        assert_eq!(original.wait(4, &times), Duration::from_secs(2));
+        assert_eq!(original.wait(5, &times), Duration::from_secs(1_800));
+        assert_eq!(original.wait(6, &times), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn rejects_speeds_and_delays_out_of_range() {
+        assert!(query(None, None, Timing::Fixed).check().is_ok());
+        assert!(query(Some(0.0), None, Timing::Fixed).check().is_err());
+        assert!(query(Some(f64::NAN), None, Timing::Fixed).check().is_err());
+        assert!(query(None, Some(-1.0), Timing::Fixed).check().is_err());
+        assert!(query(None, Some(MAX_DELAY + 1.0), Timing::Fixed)
+            .check()
+            .is_err());
+    }
+
+    #[test]
+    fn the_page_connects_to_the_replay() {
+        let page = replay_page(None);
+        assert!(page.contains("'/ws/replay/' + channel + window.location.search"));
+        assert!(!page.contains(OVERLAY_SOCKET));
+    }
+}
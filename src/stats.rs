@@ -0,0 +1,363 @@
+use crate::error::WarError;
+use crate::store::WarStore;
+use crate::track::Track;
+use crate::war::{Phase, WarData};
+use actix_web::{get, web, HttpResponse, Responder, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+#[derive(Deserialize)]
+pub struct TrackQuery {
+    /// Team to report for; without it every war counts from the home side.
+    tag: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TrackStats {
+    track: Track,
+    name: &'static str,
+    races: u32,
+    total_diff: i64,
+    /// Rounded to a tenth.
+    average_diff: f64,
+}
+
+/// +1 if `tag` is the home team of `war`, -1 if it is the enemy.
+fn side(war: &WarData, tag: &str) -> Option<i64> {
+    if war.tag.eq_ignore_ascii_case(tag) {
+        Some(1)
+    } else if war.enemy_tag.eq_ignore_ascii_case(tag) {
+        Some(-1)
+    } else {
+        None
+    }
+}
+
+/// Rounded to a tenth.
+fn average(total: i64, count: u32) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    (total as f64 / f64::from(count) * 10.0).round() / 10.0
+}
+
+/// Average diff per track, best first.
+fn per_track(wars: &[WarData], tag: Option<&str>) -> Vec<TrackStats> {
+    let mut totals: BTreeMap<Track, (u32, i64)> = BTreeMap::new();
+    for war in wars {
+        let sign = match tag {
+            None => 1,
+            Some(tag) => match side(war, tag) {
+                Some(sign) => sign,
+                None => continue,
+            },
+        };
+        for (track, diff) in war.tracks.iter().zip(&war.diff) {
+            if let Some(track) = track {
+                let entry = totals.entry(*track).or_default();
+                entry.0 += 1;
+                entry.1 += sign * i64::from(*diff);
+            }
+        }
+    }
+
+    let mut stats: Vec<TrackStats> = totals
+        .into_iter()
+        .map(|(track, (races, total_diff))| TrackStats {
+            track,
+            name: track.name(),
+            races,
+            total_diff,
+            average_diff: average(total_diff, races),
+        })
+        .collect();
+    stats.sort_by(|a, b| b.average_diff.total_cmp(&a.average_diff));
+    stats
+}
+
+#[get("/api/stats/tracks")]
+async fn tracks(
+    store: web::Data<dyn WarStore>,
+    query: web::Query<TrackQuery>,
+) -> Result<impl Responder> {
+    let tag = query.tag.as_deref();
+    // Finished wars are counted from the archive, which outlives them.
+    let mut wars = store.wars().await?;
+    wars.retain(|war| war.phase() != Phase::Final);
+    let archived = match tag {
+        Some(tag) => store.archived_by_tag(tag).await?,
+        None => store.archived_all().await?,
+    };
+    wars.extend(archived.into_iter().map(|archived| archived.war));
+    Ok(HttpResponse::Ok().json(per_track(&wars, tag)))
+}
+
+/// How a team fared over a set of finished wars.
+#[derive(Serialize, Default, Clone, Copy)]
+pub struct Record {
+    pub wars: u32,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// Final diff per war, penalties included. Rounded to a tenth.
+    pub average_diff: f64,
+    /// Diff per race played. Rounded to a tenth.
+    pub average_race_diff: f64,
+}
+
+/// Running totals behind a [`Record`].
+#[derive(Default)]
+struct Tally {
+    wars: u32,
+    wins: u32,
+    losses: u32,
+    draws: u32,
+    total_diff: i64,
+    races: u32,
+    race_diff: i64,
+}
+
+impl Tally {
+    fn add(&mut self, war: &WarData, sign: i64) {
+        let (home, enemy) = war.totals();
+        let diff = sign * i64::from(home - enemy);
+        self.wars += 1;
+        match diff {
+            d if d > 0 => self.wins += 1,
+            d if d < 0 => self.losses += 1,
+            _ => self.draws += 1,
+        }
+        self.total_diff += diff;
+        self.races += war.diff.len() as u32;
+        self.race_diff += sign * war.diff.iter().map(|&d| i64::from(d)).sum::<i64>();
+    }
+
+    fn record(&self) -> Record {
+        Record {
+            wars: self.wars,
+            wins: self.wins,
+            losses: self.losses,
+            draws: self.draws,
+            average_diff: average(self.total_diff, self.wars),
+            average_race_diff: average(self.race_diff, self.races),
+        }
+    }
+}
+
+/// `tag`'s record against `enemy_tag` in the archive.
+pub async fn head_to_head(
+    store: &dyn WarStore,
+    tag: &str,
+    enemy_tag: &str,
+) -> Result<Record, WarError> {
+    let mut tally = Tally::default();
+    for archived in store.archived_by_tag(tag).await? {
+        let war = &archived.war;
+        if let (Some(sign), Some(_)) = (side(war, tag), side(war, enemy_tag)) {
+            tally.add(war, sign);
+        }
+    }
+    Ok(tally.record())
+}
+
+#[derive(Serialize)]
+struct Opponent {
+    enemy_tag: String,
+    #[serde(flatten)]
+    record: Record,
+}
+
+#[derive(Serialize)]
+struct TeamStats {
+    tag: String,
+    #[serde(flatten)]
+    record: Record,
+    /// Most played first.
+    opponents: Vec<Opponent>,
+}
+
+#[derive(Serialize)]
+struct HeadToHead {
+    tag: String,
+    enemy_tag: String,
+    #[serde(flatten)]
+    record: Record,
+}
+
+/// A team's record over its archived wars, overall and per opponent.
+#[get("/api/stats/teams/{tag}")]
+async fn team(store: web::Data<dyn WarStore>, path: web::Path<String>) -> Result<impl Responder> {
+    let tag = path.into_inner();
+    let mut overall = Tally::default();
+    // Keyed by the lowercased tag, holding it as first seen.
+    let mut opponents: BTreeMap<String, (String, Tally)> = BTreeMap::new();
+    for archived in store.archived_by_tag(&tag).await? {
+        let war = &archived.war;
+        let Some(sign) = side(war, &tag) else {
+            continue;
+        };
+        let enemy_tag = if sign > 0 { &war.enemy_tag } else { &war.tag };
+        overall.add(war, sign);
+        opponents
+            .entry(enemy_tag.to_ascii_lowercase())
+            .or_insert_with(|| (enemy_tag.clone(), Tally::default()))
+            .1
+            .add(war, sign);
+    }
+
+    let mut opponents: Vec<Opponent> = opponents
+        .into_values()
+        .map(|(enemy_tag, tally)| Opponent {
+            enemy_tag,
+            record: tally.record(),
+        })
+        .collect();
+    opponents.sort_by_key(|opponent| Reverse(opponent.record.wars));
+    Ok(HttpResponse::Ok().json(TeamStats {
+        tag,
+        record: overall.record(),
+        opponents,
+    }))
+}
+
+#[get("/api/stats/teams/{tag}/vs/{enemy_tag}")]
+async fn versus(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let (tag, enemy_tag) = path.into_inner();
+    let record = head_to_head(store.get_ref(), &tag, &enemy_tag).await?;
+    Ok(HttpResponse::Ok().json(HeadToHead {
+        tag,
+        enemy_tag,
+        record,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::ArchivedWar;
+    use crate::history::History;
+    use crate::memory_store::MemoryStore;
+    use crate::testing::war;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    /// ABC beating XYZ over 12 races, XYZ at home beating ABC over 2, and
+    /// abc drawing DEF; each on a channel of its own.
+    async fn archive() -> Arc<dyn WarStore> {
+        let store: Arc<dyn WarStore> = Arc::new(MemoryStore::default());
+        let mut home_win = war(0);
+        home_win.tag = "XYZ".into();
+        home_win.enemy_tag = "ABC".into();
+        home_win.push_race(60.0, 22.0, None);
+        home_win.push_race(60.0, 22.0, None);
+        home_win.finished = true;
+        let mut draw = war(0);
+        draw.tag = "abc".into();
+        draw.enemy_tag = "DEF".into();
+        draw.push_race(41.0, 41.0, None);
+        draw.finished = true;
+        for (channel_id, war) in [("a", war(12)), ("b", home_win), ("c", draw)] {
+            let archived = ArchivedWar::of(channel_id, &war, &History::default()).unwrap();
+            store.archive(&archived).await.unwrap();
+        }
+        store
+    }
+
+    #[test]
+    fn averages_each_track_from_the_teams_side() {
+        let mut first = war(0);
+        first.push_race(50.0, 32.0, Some(Track::MarioCircuit));
+        first.push_race(40.0, 42.0, Some(Track::MarioCircuit));
+        first.push_race(41.0, 41.0, None);
+        first.push_race(60.0, 22.0, Some(Track::RainbowRoad));
+        let mut second = war(0);
+        second.tag = "XYZ".into();
+        second.enemy_tag = "ABC".into();
+        second.push_race(32.0, 50.0, Some(Track::MarioCircuit));
+
+        let stats = per_track(&[first.clone(), second.clone()], Some("abc"));
+        let rows: Vec<_> = stats
+            .iter()
+            .map(|row| (row.track, row.races, row.total_diff, row.average_diff))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (Track::RainbowRoad, 1, 38, 38.0),
+                (Track::MarioCircuit, 3, 34, 11.3),
+            ]
+        );
+
+        let stats = per_track(&[first, second], None);
+        assert_eq!(stats[1].total_diff, -2);
+    }
+
+    #[actix_web::test]
+    async fn head_to_head_counts_both_sides() {
+        let store = archive().await;
+        let record = head_to_head(store.as_ref(), "ABC", "xyz").await.unwrap();
+        assert_eq!(
+            (record.wars, record.wins, record.losses, record.draws),
+            (2, 1, 1, 0)
+        );
+        assert_eq!(record.average_diff, 70.0);
+        assert_eq!(record.average_race_diff, 10.0);
+
+        let record = head_to_head(store.as_ref(), "XYZ", "ABC").await.unwrap();
+        assert_eq!(record.average_diff, -70.0);
+
+        let record = head_to_head(store.as_ref(), "ABC", "DEF").await.unwrap();
+        assert_eq!((record.wars, record.draws), (1, 1));
+
+        let record = head_to_head(store.as_ref(), "ABC", "QQQ").await.unwrap();
+        assert_eq!((record.wars, record.average_diff), (0, 0.0));
+    }
+
+    #[actix_web::test]
+    async fn teams_are_reported_per_opponent() {
+        let store = archive().await;
+        let app =
+            test::init_service(App::new().app_data(web::Data::from(store)).service(team)).await;
+        let req = TestRequest::get().uri("/api/stats/teams/abc").to_request();
+        let body: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            (
+                &body["wars"],
+                &body["wins"],
+                &body["losses"],
+                &body["draws"]
+            ),
+            (
+                &Value::from(3),
+                &Value::from(1),
+                &Value::from(1),
+                &Value::from(1)
+            )
+        );
+        let opponents: Vec<_> = body["opponents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|opponent| {
+                (
+                    opponent["enemy_tag"].as_str().unwrap(),
+                    opponent["wars"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(opponents, [("XYZ", 2), ("DEF", 1)]);
+    }
+
+    #[test]
+    fn averages_round_to_a_tenth() {
+        assert_eq!(average(0, 0), 0.0);
+        assert_eq!(average(10, 3), 3.3);
+        assert_eq!(average(-20, 3), -6.7);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::archive::ArchivedWar;
+use crate::error::WarError;
+use crate::history::{History, Step};
+use crate::war::WarData;
+use async_trait::async_trait;
+
+/// A change to apply to the current war, or to `None` if there is none yet.
+///
+/// May run more than once if the war changes underneath it.
+pub type Edit<'a> = &'a (dyn Fn(Option<WarData>) -> Result<WarData, WarError> + Send + Sync);
+
+/// Where wars live, keyed by channel id.
+///
+/// Handlers and the hub only talk to this trait; `main` picks the backend.
+#[async_trait]
+pub trait WarStore: Send + Sync {
+    /// The war for `channel_id`.
+    async fn get(&self, channel_id: &str) -> Result<WarData, WarError>;
+
+    /// Replace the war for `channel_id`.
+    async fn put(&self, channel_id: &str, war: &WarData) -> Result<(), WarError>;
+
+    /// Every stored war, for statistics. Entries that can't be read are
+    /// skipped.
+    async fn wars(&self) -> Result<Vec<WarData>, WarError>;
+
+    /// Atomically replace the war for `channel_id` with the result of `edit`,
+    /// recording it as a revision described by `action`.
+    async fn update(
+        &self,
+        channel_id: &str,
+        action: &str,
+        edit: Edit<'_>,
+    ) -> Result<WarData, WarError>;
+
+    /// Revisions recorded by [`update`](Self::update). Writes that bypass it,
+    /// like the bot's, aren't in here.
+    async fn history(&self, channel_id: &str) -> Result<History, WarError>;
+
+    /// Undo or redo: make the neighbouring revision the live war.
+    async fn step(&self, channel_id: &str, step: Step) -> Result<WarData, WarError>;
+
+    /// Keep a finished war in its channel's archive, replacing the entry
+    /// with the same id.
+    async fn archive(&self, war: &ArchivedWar) -> Result<(), WarError>;
+
+    /// Up to `limit` archived wars of the channel, most recently archived
+    /// first, skipping `offset`; and how many there are in all.
+    async fn archived(
+        &self,
+        channel_id: &str,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<ArchivedWar>, usize), WarError>;
+
+    /// One archived war by id.
+    async fn archived_war(&self, channel_id: &str, id: &str) -> Result<ArchivedWar, WarError>;
+
+    /// An archived war of any channel by id; ids hash the channel in, so
+    /// they don't clash across channels.
+    async fn archived_by_id(&self, id: &str) -> Result<ArchivedWar, WarError>;
+
+    /// Archived wars of every channel.
+    async fn archived_all(&self) -> Result<Vec<ArchivedWar>, WarError>;
+
+    /// Archived wars of every channel with `tag` on either side, ignoring
+    /// ASCII case.
+    async fn archived_by_tag(&self, tag: &str) -> Result<Vec<ArchivedWar>, WarError>;
+
+    /// The channel's write token, `None` until someone claims the channel.
+    ///
+    /// Kept apart from the war so it never ends up in what viewers see.
+    async fn token(&self, channel_id: &str) -> Result<Option<String>, WarError>;
+
+    /// Store the channel's write token. Unless `replace` is set this only
+    /// succeeds for unclaimed channels; returns whether it was stored.
+    async fn set_token(
+        &self,
+        channel_id: &str,
+        token: &str,
+        replace: bool,
+    ) -> Result<bool, WarError>;
+
+    /// Remove the channel's token if it is still `token`, undoing a claim
+    /// whose write failed.
+    async fn release_token(&self, channel_id: &str, token: &str) -> Result<(), WarError>;
+
+    /// Change feed for `channel_id`.
+    async fn watch(&self, channel_id: &str) -> Box<dyn Watch>;
+}
+
+#[async_trait]
+pub trait Watch: Send {
+    /// Resolve once the war may have changed.
+    async fn changed(&mut self);
+}
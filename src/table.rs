@@ -0,0 +1,372 @@
+use crate::api::MAX_PENALTY;
+use crate::error::WarError;
+use crate::format::{FormatInput, WarFormat, MAX_RACES, MAX_TEAM_SIZE};
+use crate::store::WarStore;
+use crate::war::{Penalty, Team, WarData};
+use actix_web::{get, web, HttpResponse, Responder, Result};
+
+/// A table as parsed, before it becomes a war.
+pub struct Table {
+    pub home: TeamTable,
+    pub enemy: TeamTable,
+}
+
+pub struct TeamTable {
+    pub tag: String,
+    /// Names and points per race, in table order.
+    pub players: Vec<(String, Vec<u32>)>,
+    /// Points the team's penalty lines take off: `Penalty -10` is 10, and a
+    /// positive line is a bonus, counted negative.
+    pub penalty: i32,
+}
+
+impl TeamTable {
+    pub fn names(&self) -> Vec<String> {
+        self.players.iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+/// Read a table in the plain text format of the community table tool
+/// (Lorenzi's table maker). It must have exactly two teams, the home team
+/// first:
+///
+/// ```text
+/// #title ABC vs XYZ
+/// ABC - Alphabet
+/// Mario 15|12|10
+/// Luigi [de] 9|8|12
+/// Penalty -10
+///
+/// XYZ
+/// Peach 7|6|5
+/// ```
+///
+/// The first line after a blank one opens a team, as does any line without
+/// a score: `TAG - Name` with an optional trailing `#rrggbb` color. Player
+/// lines end with their points, per race separated by `|` or `+`; a
+/// `[flag]` before the points is ignored. A `Penalty` line adds its points
+/// to the team, so usually takes them off. A team without players hasn't
+/// raced yet.
+pub fn parse(text: &str) -> Result<Table, WarError> {
+    let mut teams: Vec<TeamTable> = Vec::new();
+    let mut in_team = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            in_team = false;
+            continue;
+        }
+        // `#title` and other directives only matter to the table tool.
+        if line.starts_with('#') {
+            continue;
+        }
+        let scored = scored(line).filter(|_| in_team);
+        let (Some((name, points)), Some(team)) = (scored, teams.last_mut()) else {
+            teams.push(TeamTable {
+                tag: header_tag(line),
+                players: Vec::new(),
+                penalty: 0,
+            });
+            in_team = true;
+            continue;
+        };
+
+        if name.eq_ignore_ascii_case("penalty") || name.eq_ignore_ascii_case("pen") {
+            let total = points
+                .iter()
+                .try_fold(team.penalty, |total, &amount| total.checked_sub(amount));
+            team.penalty = match total {
+                Some(total) if (-MAX_PENALTY..=MAX_PENALTY).contains(&total) => total,
+                _ => {
+                    return Err(bad(format!(
+                    "line {}: penalties must add up to between -{MAX_PENALTY} and {MAX_PENALTY}",
+                    number + 1
+                )))
+                }
+            };
+            continue;
+        }
+        let points = points
+            .iter()
+            .map(|&p| u32::try_from(p))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                bad(format!(
+                    "line {}: points of {name:?} must not be negative",
+                    number + 1
+                ))
+            })?;
+        team.players.push((name.to_string(), points));
+    }
+
+    if teams.len() != 2 {
+        return Err(bad(format!("expected 2 teams, found {}", teams.len())));
+    }
+    let enemy = teams.pop().unwrap();
+    let home = teams.pop().unwrap();
+    Ok(Table { home, enemy })
+}
+
+/// Split a player or penalty line into its name and points.
+fn scored(line: &str) -> Option<(&str, Vec<i32>)> {
+    let (name, points) = line.rsplit_once(char::is_whitespace)?;
+    let points = points
+        .split(['|', '+'])
+        .map(|p| p.parse::<i32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let mut name = name.trim_end();
+    if let Some(start) = name.rfind(" [").filter(|_| name.ends_with(']')) {
+        name = name[..start].trim_end();
+    }
+    Some((name, points))
+}
+
+/// The tag of a team line: `ABC - Alphabet #ff0000` is `ABC`.
+fn header_tag(line: &str) -> String {
+    let line = match line.rsplit_once(char::is_whitespace) {
+        Some((rest, color)) if color.starts_with('#') => rest,
+        _ => line,
+    };
+    let tag = line.split_once(" - ").map_or(line, |(tag, _)| tag);
+    tag.trim().to_string()
+}
+
+impl Table {
+    /// Races: the most any player has points for.
+    fn races(&self) -> usize {
+        self.home
+            .players
+            .iter()
+            .chain(&self.enemy.players)
+            .map(|(_, points)| points.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The war the table describes. Without a format, the preset for the
+    /// smaller team is used, stretched to the races played; tables without
+    /// players get the default format.
+    pub fn war(&self, format: Option<WarFormat>) -> Result<WarData, WarError> {
+        let races = self.races();
+        if races > MAX_RACES as usize {
+            return Err(bad(format!("tables hold at most {MAX_RACES} races")));
+        }
+        let sizes = (self.home.players.len(), self.enemy.players.len());
+        let mut format = match format {
+            Some(format) => format,
+            None if sizes.0 == 0 || sizes.1 == 0 => WarFormat::default(),
+            None => {
+                let size = sizes.0.min(sizes.1).min(MAX_TEAM_SIZE as usize);
+                FormatInput::Preset(format!("{size}v{size}")).build()?
+            }
+        };
+        format.races = format.races.max(races as u32);
+
+        let mut war = WarData::new(self.home.tag.clone(), self.enemy.tag.clone(), format);
+        let (home, enemy) = (self.home.names(), self.enemy.names());
+        war.set_roster(&home, &enemy);
+        let team_points = |team: &TeamTable, race: usize| -> Result<f64, WarError> {
+            team.players
+                .iter()
+                .filter_map(|(_, points)| points.get(race))
+                .try_fold(0u32, |sum, &points| sum.checked_add(points))
+                .map(f64::from)
+                .ok_or_else(|| bad(format!("{} scores too much in race {}", team.tag, race + 1)))
+        };
+        for race in 0..races {
+            war.push_race(
+                team_points(&self.home, race)?,
+                team_points(&self.enemy, race)?,
+                None,
+            );
+        }
+        // The roster follows table order, home team first.
+        let tables = self.home.players.iter().chain(&self.enemy.players);
+        for (player, (_, points)) in war.roster.iter_mut().zip(tables) {
+            for (slot, points) in player.points.iter_mut().zip(points) {
+                *slot = Some(*points);
+            }
+        }
+        for (team, table) in [(Team::Home, &self.home), (Team::Enemy, &self.enemy)] {
+            if table.penalty != 0 {
+                war.penalties
+                    .push(Penalty::now(team, table.penalty, races as u32, None));
+            }
+        }
+        Ok(war)
+    }
+}
+
+/// `war` as a table. Wars without a roster get one line per team, named
+/// after the tag; wars without races only their team lines.
+pub fn render(war: &WarData) -> String {
+    let mut out = format!("#title {} vs {}\n", war.tag, war.enemy_tag);
+    let teams = [
+        (Team::Home, &war.tag, &war.home_score),
+        (Team::Enemy, &war.enemy_tag, &war.enemy_score),
+    ];
+    for (i, (team, tag, scores)) in teams.into_iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(tag);
+        out.push('\n');
+
+        let players: Vec<_> = if war.race_count() == 0 {
+            Vec::new()
+        } else {
+            war.roster.iter().filter(|p| p.team == team).collect()
+        };
+        if players.is_empty() && war.race_count() > 0 {
+            let points: Vec<u32> = scores.iter().map(|s| s.round() as u32).collect();
+            out.push_str(&format!("{tag} {}\n", joined(&points)));
+        }
+        for player in players {
+            let points: Vec<u32> = player.points.iter().map(|p| p.unwrap_or(0)).collect();
+            out.push_str(&format!("{} {}\n", player.name, joined(&points)));
+        }
+
+        let penalty = war.penalty(team);
+        if penalty != 0 {
+            out.push_str(&format!("Penalty {}\n", -penalty));
+        }
+    }
+    out
+}
+
+/// `15|12|10`, or `0` for a player without points.
+fn joined(points: &[u32]) -> String {
+    if points.is_empty() {
+        return "0".to_string();
+    }
+    let points: Vec<String> = points.iter().map(u32::to_string).collect();
+    points.join("|")
+}
+
+fn bad(reason: String) -> WarError {
+    WarError::BadRequest(reason)
+}
+
+fn text(war: &WarData) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(render(war))
+}
+
+/// The channel's current war as a table.
+#[get("/api/{channel_id}/table")]
+async fn live(store: web::Data<dyn WarStore>, path: web::Path<String>) -> Result<impl Responder> {
+    let war = store.get(&path.into_inner()).await?;
+    Ok(text(&war))
+}
+
+#[get("/api/{channel_id}/history/{war_id}/table")]
+async fn archived(
+    store: web::Data<dyn WarStore>,
+    path: web::Path<(String, String)>,
+) -> Result<impl Responder> {
+    let (channel_id, war_id) = path.into_inner();
+    let archived = store.archived_war(&channel_id, &war_id).await?;
+    Ok(text(&archived.war))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::war;
+
+    const TABLE: &str = "#title ABC vs XYZ
+ABC - Alphabet #ff0000
+Mario 15|12|10
+Luigi [de] 9|8|12
+Penalty -10
+
+XYZ
+Peach 7+6+5
+Daisy 1|2|1
+";
+
+    #[test]
+    fn parses_teams_players_and_penalties() {
+        let table = parse(TABLE).unwrap();
+        assert_eq!(table.home.tag, "ABC");
+        assert_eq!(table.home.names(), vec!["Mario", "Luigi"]);
+        assert_eq!(table.home.players[1].1, vec![9, 8, 12]);
+        assert_eq!(table.home.penalty, 10);
+        assert_eq!(table.enemy.tag, "XYZ");
+        assert_eq!(table.enemy.players[0].1, vec![7, 6, 5]);
+        assert_eq!(table.enemy.penalty, 0);
+    }
+
+    #[test]
+    fn numbered_team_names_are_headers() {
+        let table = parse("ABC - Team 7\nMario 15\n\nXYZ - Squad 2\nPeach 7\n").unwrap();
+        assert_eq!(table.home.tag, "ABC");
+        assert_eq!(table.enemy.tag, "XYZ");
+        assert_eq!(table.home.names(), vec!["Mario"]);
+    }
+
+    #[test]
+    fn rejects_tables_without_two_teams() {
+        assert!(parse("ABC\nMario 15\n").is_err());
+        assert!(parse("ABC\nMario -1\n\nXYZ\nPeach 7\n").is_err());
+    }
+
+    #[test]
+    fn penalties_are_bounded() {
+        let table = |penalty: &str| parse(&format!("ABC\nMario 15\n{penalty}\n\nXYZ\nPeach 7\n"));
+        assert_eq!(table("Penalty -9999").unwrap().home.penalty, MAX_PENALTY);
+        assert!(table("Penalty -2147483648").is_err());
+        assert!(table("Penalty -9999|-1").is_err());
+        assert!(table("Penalty -9999\nPen -1").is_err());
+        assert!(table("Penalty 2147483647|1").is_err());
+    }
+
+    #[test]
+    fn team_points_must_fit() {
+        let table = parse("ABC\nMario 4000000000\nLuigi 400000000\n\nXYZ\nPeach 7\n");
+        assert!(table.is_err(), "points past i32 are not a score");
+        let mut table = parse("ABC\nMario 1\nLuigi 1\n\nXYZ\nPeach 7\n").unwrap();
+        table.home.players[0].1[0] = u32::MAX;
+        assert!(table.war(None).is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let war = parse(TABLE).unwrap().war(None).unwrap();
+        assert_eq!(war.race_count(), 3);
+        assert_eq!(war.home_score, vec![24.0, 20.0, 22.0]);
+        assert_eq!(war.totals(), (56, 22));
+        assert_round_trips(&war);
+    }
+
+    #[test]
+    fn bonuses_round_trip() {
+        let table = parse(&format!("{TABLE}Penalty 5\n")).unwrap();
+        assert_eq!(table.enemy.penalty, -5);
+        let war = table.war(None).unwrap();
+        assert_eq!(war.totals(), (56, 27));
+        assert_round_trips(&war);
+    }
+
+    fn assert_round_trips(war: &WarData) {
+        let rendered = render(war);
+        let again = parse(&rendered).unwrap().war(None).unwrap();
+        assert_eq!(again.home_score, war.home_score);
+        assert_eq!(again.enemy_score, war.enemy_score);
+        assert_eq!(again.totals(), war.totals());
+        assert_eq!(render(&again), rendered);
+    }
+
+    #[test]
+    fn wars_without_races_round_trip() {
+        let mut war = war(0);
+        war.set_roster(&["Mario".to_string()], &["Peach".to_string()]);
+        let again = parse(&render(&war)).unwrap().war(None).unwrap();
+        assert_eq!(again.race_count(), 0);
+        assert_eq!(
+            (again.tag.as_str(), again.enemy_tag.as_str()),
+            ("ABC", "XYZ")
+        );
+    }
+}
@@ -0,0 +1,24 @@
+use crate::format::WarFormat;
+use crate::war::WarData;
+
+/// ABC vs XYZ in `format`, `races` races in, each won 50 to 32.
+pub fn war_in(format: WarFormat, races: usize) -> WarData {
+    let mut war = WarData::new("ABC".to_string(), "XYZ".to_string(), format);
+    for _ in 0..races {
+        war.push_race(50.0, 32.0, None);
+    }
+    war
+}
+
+/// [`war_in`] the default 12 race 6v6.
+pub fn war(races: usize) -> WarData {
+    war_in(WarFormat::default(), races)
+}
+
+/// A format of `races` races, otherwise the default.
+pub fn races(races: u32) -> WarFormat {
+    WarFormat {
+        races,
+        ..WarFormat::default()
+    }
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Declares [`Track`] along with its abbreviation and full name tables.
+macro_rules! tracks {
+    ($($variant:ident => $abbr:literal, $name:literal;)*) => {
+        /// A Mario Kart 8 Deluxe course, base game and Booster Course Pass.
+        ///
+        /// Stored and sent as its abbreviation, e.g. `"rMC"`.
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub enum Track {
+            $($variant,)*
+        }
+
+        impl Track {
+            pub const ALL: &'static [Track] = &[$(Track::$variant,)*];
+
+            /// The abbreviation used in tables and bots, e.g. `"dBB"`.
+            pub fn abbr(self) -> &'static str {
+                match self {
+                    $(Track::$variant => $abbr,)*
+                }
+            }
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(Track::$variant => $name,)*
+                }
+            }
+        }
+    };
+}
+
+tracks! {
+    MarioKartStadium => "MKS", "Mario Kart Stadium";
+    WaterPark => "WP", "Water Park";
+    SweetSweetCanyon => "SSC", "Sweet Sweet Canyon";
+    ThwompRuins => "TR", "Thwomp Ruins";
+    MarioCircuit => "MC", "Mario Circuit";
+    ToadHarbor => "TH", "Toad Harbor";
+    TwistedMansion => "TM", "Twisted Mansion";
+    ShyGuyFalls => "SGF", "Shy Guy Falls";
+    SunshineAirport => "SA", "Sunshine Airport";
+    DolphinShoals => "DS", "Dolphin Shoals";
+    Electrodrome => "Ed", "Electrodrome";
+    MountWario => "MW", "Mount Wario";
+    CloudtopCruise => "CC", "Cloudtop Cruise";
+    BoneDryDunes => "BDD", "Bone-Dry Dunes";
+    BowsersCastle => "BC", "Bowser's Castle";
+    RainbowRoad => "RR", "Rainbow Road";
+    WiiMooMooMeadows => "rMMM", "Wii Moo Moo Meadows";
+    GbaMarioCircuit => "rMC", "GBA Mario Circuit";
+    DsCheepCheepBeach => "rCCB", "DS Cheep Cheep Beach";
+    N64ToadsTurnpike => "rTT", "N64 Toad's Turnpike";
+    GcnDryDryDesert => "rDDD", "GCN Dry Dry Desert";
+    SnesDonutPlains3 => "rDP3", "SNES Donut Plains 3";
+    N64RoyalRaceway => "rRRy", "N64 Royal Raceway";
+    ThreeDsDkJungle => "rDKJ", "3DS DK Jungle";
+    DsWarioStadium => "rWS", "DS Wario Stadium";
+    GcnSherbetLand => "rSL", "GCN Sherbet Land";
+    ThreeDsMusicPark => "rMP", "3DS Music Park";
+    N64YoshiValley => "rYV", "N64 Yoshi Valley";
+    DsTickTockClock => "rTTC", "DS Tick-Tock Clock";
+    ThreeDsPiranhaPlantSlide => "rPPS", "3DS Piranha Plant Slide";
+    WiiGrumbleVolcano => "rGV", "Wii Grumble Volcano";
+    N64RainbowRoad => "rRRd", "N64 Rainbow Road";
+    GcnYoshiCircuit => "dYC", "GCN Yoshi Circuit";
+    ExcitebikeArena => "dEA", "Excitebike Arena";
+    DragonDriftway => "dDD", "Dragon Driftway";
+    MuteCity => "dMC", "Mute City";
+    WiiWariosGoldMine => "dWGM", "Wii Wario's Gold Mine";
+    SnesRainbowRoad => "dRR", "SNES Rainbow Road";
+    IceIceOutpost => "dIIO", "Ice Ice Outpost";
+    HyruleCircuit => "dHC", "Hyrule Circuit";
+    GcnBabyPark => "dBP", "GCN Baby Park";
+    GbaCheeseLand => "dCL", "GBA Cheese Land";
+    WildWoods => "dWW", "Wild Woods";
+    AnimalCrossing => "dAC", "Animal Crossing";
+    ThreeDsNeoBowserCity => "dNBC", "3DS Neo Bowser City";
+    GbaRibbonRoad => "dRiR", "GBA Ribbon Road";
+    SuperBellSubway => "dSBS", "Super Bell Subway";
+    BigBlue => "dBB", "Big Blue";
+    TourParisPromenade => "bPP", "Tour Paris Promenade";
+    ThreeDsToadCircuit => "bTC", "3DS Toad Circuit";
+    N64ChocoMountain => "bCMo", "N64 Choco Mountain";
+    WiiCoconutMall => "bCMa", "Wii Coconut Mall";
+    TourTokyoBlur => "bTB", "Tour Tokyo Blur";
+    DsShroomRidge => "bSR", "DS Shroom Ridge";
+    GbaSkyGarden => "bSG", "GBA Sky Garden";
+    NinjaHideaway => "bNH", "Ninja Hideaway";
+    TourNewYorkMinute => "bNYM", "Tour New York Minute";
+    SnesMarioCircuit3 => "bMC3", "SNES Mario Circuit 3";
+    N64KalimariDesert => "bKD", "N64 Kalimari Desert";
+    DsWaluigiPinball => "bWP", "DS Waluigi Pinball";
+    TourSydneySprint => "bSS", "Tour Sydney Sprint";
+    GbaSnowLand => "bSL", "GBA Snow Land";
+    WiiMushroomGorge => "bMG", "Wii Mushroom Gorge";
+    SkyHighSundae => "bSHS", "Sky-High Sundae";
+    TourLondonLoop => "bLL", "Tour London Loop";
+    GbaBooLake => "bBL", "GBA Boo Lake";
+    ThreeDsRockRockMountain => "bRRM", "3DS Rock Rock Mountain";
+    WiiMapleTreeway => "bMT", "Wii Maple Treeway";
+    TourBerlinByways => "bBB", "Tour Berlin Byways";
+    DsPeachGardens => "bPG", "DS Peach Gardens";
+    MerryMountain => "bMM", "Merry Mountain";
+    ThreeDsRainbowRoad => "bRR7", "3DS Rainbow Road";
+    TourAmsterdamDrift => "bAD", "Tour Amsterdam Drift";
+    GbaRiversidePark => "bRP", "GBA Riverside Park";
+    WiiDkSummit => "bDKS", "Wii DK Summit";
+    YoshisIsland => "bYI", "Yoshi's Island";
+    TourBangkokRush => "bBR", "Tour Bangkok Rush";
+    DsMarioCircuit => "bMC", "DS Mario Circuit";
+    GcnWaluigiStadium => "bWS", "GCN Waluigi Stadium";
+    TourSingaporeSpeedway => "bSSy", "Tour Singapore Speedway";
+    TourAthensDash => "bAtD", "Tour Athens Dash";
+    GcnDaisyCruiser => "bDC", "GCN Daisy Cruiser";
+    WiiMoonviewHighway => "bMH", "Wii Moonview Highway";
+    SqueakyCleanSprint => "bSCS", "Squeaky Clean Sprint";
+    TourLosAngelesLaps => "bLAL", "Tour Los Angeles Laps";
+    GbaSunsetWilds => "bSW", "GBA Sunset Wilds";
+    WiiKoopaCape => "bKC", "Wii Koopa Cape";
+    TourVancouverVelocity => "bVV", "Tour Vancouver Velocity";
+    TourRomeAvanti => "bRA", "Tour Rome Avanti";
+    GcnDkMountain => "bDKM", "GCN DK Mountain";
+    WiiDaisyCircuit => "bDCt", "Wii Daisy Circuit";
+    PiranhaPlantCove => "bPPC", "Piranha Plant Cove";
+    TourMadridDrive => "bMD", "Tour Madrid Drive";
+    ThreeDsRosalinasIceWorld => "bRIW", "3DS Rosalina's Ice World";
+    SnesBowserCastle3 => "bBC3", "SNES Bowser Castle 3";
+    WiiRainbowRoad => "bRRw", "Wii Rainbow Road";
+}
+
+impl FromStr for Track {
+    type Err = String;
+
+    /// Accepts the abbreviation or the full name, ignoring case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Track::ALL
+            .iter()
+            .copied()
+            .find(|track| {
+                track.abbr().eq_ignore_ascii_case(s) || track.name().eq_ignore_ascii_case(s)
+            })
+            .ok_or_else(|| format!("unknown track {s:?}"))
+    }
+}
+
+impl fmt::Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.abbr())
+    }
+}
+
+impl Serialize for Track {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.abbr())
+    }
+}
+
+impl<'de> Deserialize<'de> for Track {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_abbreviations_and_names_in_any_case() {
+        assert_eq!("rMC".parse(), Ok(Track::GbaMarioCircuit));
+        assert_eq!("RMC".parse(), Ok(Track::GbaMarioCircuit));
+        assert_eq!(" gba mario circuit ".parse(), Ok(Track::GbaMarioCircuit));
+        assert_eq!("MC".parse(), Ok(Track::MarioCircuit));
+        assert!("Mario Circuit 2".parse::<Track>().is_err());
+    }
+
+    #[test]
+    fn abbreviations_and_names_are_unique() {
+        for (i, a) in Track::ALL.iter().enumerate() {
+            for b in &Track::ALL[i + 1..] {
+                assert!(!a.abbr().eq_ignore_ascii_case(b.abbr()), "{a}");
+                assert!(!a.name().eq_ignore_ascii_case(b.name()), "{a}");
+            }
+        }
+    }
+
+    #[test]
+    fn serializes_as_the_abbreviation() {
+        let json = serde_json::to_string(&Track::ThreeDsNeoBowserCity).unwrap();
+        assert_eq!(json, r#""dNBC""#);
+        assert!(serde_json::from_str::<Track>(r#""Neo Bowser City""#).is_err());
+        assert_eq!(
+            serde_json::from_str::<Track>(&json).unwrap(),
+            Track::ThreeDsNeoBowserCity
+        );
+    }
+}
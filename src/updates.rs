@@ -0,0 +1,125 @@
+use crate::config::{Config, Notifications};
+use crate::store::Watch;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::{info, warn};
+use redis::aio::{ConnectionManager, PubSubStream};
+use std::time::{Duration, Instant};
+use tokio::time::{interval, Interval};
+
+/// How long to keep polling after a subscription failed before trying to
+/// subscribe again.
+const RESUBSCRIBE_AFTER: Duration = Duration::from_secs(10);
+
+/// Change feed for a single war key.
+///
+/// Prefers a redis subscription so viewers see new data as soon as it is
+/// written, and falls back to polling whenever notifications are disabled on
+/// the server or the subscription drops.
+pub struct Updates {
+    client: redis::Client,
+    con: ConnectionManager,
+    mode: Notifications,
+    channel: String,
+    channel_id: String,
+    subscription: Option<PubSubStream>,
+    poll: Interval,
+    last_attempt: Instant,
+}
+
+impl Updates {
+    pub async fn new(
+        client: redis::Client,
+        con: ConnectionManager,
+        config: &Config,
+        channel_id: &str,
+    ) -> Self {
+        let channel = match config.notifications {
+            Notifications::Keyspace => format!(
+                "__keyspace@{}__:{channel_id}",
+                client.get_connection_info().redis_settings().db()
+            ),
+            Notifications::Pubsub => format!("{}{channel_id}", config.update_channel_prefix),
+            Notifications::Poll => String::new(),
+        };
+        let mut updates = Self {
+            client,
+            con,
+            mode: config.notifications,
+            channel,
+            channel_id: channel_id.to_owned(),
+            subscription: None,
+            poll: interval(config.poll_interval()),
+            last_attempt: Instant::now(),
+        };
+        updates.subscribe().await;
+        updates
+    }
+
+    async fn subscribe(&mut self) {
+        self.last_attempt = Instant::now();
+        if self.mode == Notifications::Poll {
+            return;
+        }
+        if self.mode == Notifications::Keyspace && !keyspace_enabled(&mut self.con).await {
+            warn!(target: &self.channel_id, "keyspace notifications disabled, polling");
+            return;
+        }
+
+        let mut pubsub = match self.client.get_async_pubsub().await {
+            Ok(v) => v,
+            Err(e) => {
+                warn!(target: &self.channel_id, "cannot subscribe, polling: {e}");
+                return;
+            }
+        };
+        if let Err(e) = pubsub.subscribe(&self.channel).await {
+            warn!(target: &self.channel_id, "cannot subscribe, polling: {e}");
+            return;
+        }
+        info!(target: &self.channel_id, "subscribed to {}", self.channel);
+        self.subscription = Some(pubsub.into_on_message());
+    }
+}
+
+#[async_trait]
+impl Watch for Updates {
+    /// Also resolves right after a subscription is lost so callers resync
+    /// anything written while it was down.
+    async fn changed(&mut self) {
+        match &mut self.subscription {
+            Some(stream) => {
+                if stream.next().await.is_none() {
+                    warn!(target: &self.channel_id, "subscription lost, polling");
+                    self.subscription = None;
+                    self.last_attempt = Instant::now();
+                }
+            }
+            None => {
+                self.poll.tick().await;
+                if self.mode != Notifications::Poll
+                    && self.last_attempt.elapsed() >= RESUBSCRIBE_AFTER
+                {
+                    self.subscribe().await;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the server emits keyspace events for string writes.
+async fn keyspace_enabled(con: &mut ConnectionManager) -> bool {
+    let reply: Vec<String> = match redis::cmd("CONFIG")
+        .arg("GET")
+        .arg("notify-keyspace-events")
+        .query_async(con)
+        .await
+    {
+        Ok(v) => v,
+        Err(_) => return false,
+    };
+    match reply.get(1) {
+        Some(flags) => flags.contains('K') && (flags.contains('A') || flags.contains('$')),
+        None => false,
+    }
+}
@@ -0,0 +1,278 @@
+use crate::error::WarError;
+use crate::war::{race_diff, OverlayData, WarData};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// Scores are stored as floats; anything closer than this is equal.
+const EPSILON: f64 = 0.01;
+
+/// What to do with a war that fails validation.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    /// Refuse to serve it.
+    Reject,
+    /// Recompute what can be derived from the scores, warn about the rest.
+    Repair,
+    /// Serve it as stored, listing the problems in `warnings`.
+    Warn,
+}
+
+impl FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(Policy::Reject),
+            "repair" => Ok(Policy::Repair),
+            "warn" => Ok(Policy::Warn),
+            _ => Err("expected one of reject, repair, warn".to_string()),
+        }
+    }
+}
+
+/// A single inconsistency in a [`WarData`]. Race numbers are 1-based.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    LengthMismatch {
+        home: usize,
+        enemy: usize,
+        diff: usize,
+    },
+    RaceTotal {
+        race: usize,
+        expected: f64,
+        actual: f64,
+    },
+    Diff {
+        race: usize,
+        expected: i32,
+        actual: i32,
+    },
+    LastDiff {
+        expected: Option<i32>,
+        actual: Option<i32>,
+    },
+    Tracks {
+        tracks: usize,
+        races: usize,
+    },
+    PlayerPoints {
+        player: String,
+        points: usize,
+        races: usize,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::LengthMismatch { home, enemy, diff } => write!(
+                f,
+                "race counts differ: {home} home scores, {enemy} enemy scores, {diff} diffs"
+            ),
+            Violation::RaceTotal {
+                race,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "race {race}: teams scored {actual} points, expected {expected}"
+            ),
+            Violation::Diff {
+                race,
+                expected,
+                actual,
+            } => write!(f, "race {race}: diff is {actual}, scores give {expected}"),
+            Violation::LastDiff { expected, actual } => {
+                write!(f, "last_diff is {actual:?}, diffs give {expected:?}")
+            }
+            Violation::Tracks { tracks, races } => {
+                write!(f, "{tracks} tracks recorded for {races} races")
+            }
+            Violation::PlayerPoints {
+                player,
+                points,
+                races,
+            } => write!(
+                f,
+                "{player} has points for {points} races, expected {races}"
+            ),
+        }
+    }
+}
+
+/// Every inconsistency in `war`.
+pub fn check(war: &WarData) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let (home, enemy, diff) = (war.home_score.len(), war.enemy_score.len(), war.diff.len());
+    if home != enemy || home != diff {
+        violations.push(Violation::LengthMismatch { home, enemy, diff });
+    }
+
+    let race_points = f64::from(war.format.race_points());
+    for (i, (home, enemy)) in war.home_score.iter().zip(&war.enemy_score).enumerate() {
+        let actual = home + enemy;
+        if (actual - race_points).abs() > EPSILON {
+            violations.push(Violation::RaceTotal {
+                race: i + 1,
+                expected: race_points,
+                actual,
+            });
+        }
+        if let Some(&actual) = war.diff.get(i) {
+            let expected = race_diff(*home, *enemy);
+            if actual != expected {
+                violations.push(Violation::Diff {
+                    race: i + 1,
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+
+    let expected = war.diff.last().copied();
+    if war.last_diff != expected {
+        violations.push(Violation::LastDiff {
+            expected,
+            actual: war.last_diff,
+        });
+    }
+
+    if war.tracks.len() != diff {
+        violations.push(Violation::Tracks {
+            tracks: war.tracks.len(),
+            races: diff,
+        });
+    }
+    for player in &war.roster {
+        if player.points.len() != diff {
+            violations.push(Violation::PlayerPoints {
+                player: player.name.clone(),
+                points: player.points.len(),
+                races: diff,
+            });
+        }
+    }
+
+    violations
+}
+
+/// Rebuild `diff` and `last_diff` from the scores, dropping races that only
+/// one team has a score for. Tracks and player points are cut or padded to
+/// match.
+pub fn repair(war: &mut WarData) {
+    let races = war.home_score.len().min(war.enemy_score.len());
+    war.home_score.truncate(races);
+    war.enemy_score.truncate(races);
+    war.diff = war
+        .home_score
+        .iter()
+        .zip(&war.enemy_score)
+        .map(|(home, enemy)| race_diff(*home, *enemy))
+        .collect();
+    war.last_diff = war.diff.last().copied();
+    war.tracks.resize(races, None);
+    for player in &mut war.roster {
+        player.points.resize(races, None);
+    }
+}
+
+/// Validate `war` under `policy` and reduce it for overlays.
+pub fn overlay(mut war: WarData, policy: Policy) -> Result<OverlayData, WarError> {
+    let mut violations = check(&war);
+    if violations.is_empty() {
+        return Ok(OverlayData::from(war));
+    }
+
+    match policy {
+        Policy::Reject => {
+            let reasons: Vec<String> = violations.iter().map(ToString::to_string).collect();
+            return Err(WarError::Invalid(reasons.join("; ")));
+        }
+        Policy::Repair => {
+            repair(&mut war);
+            violations = check(&war);
+        }
+        Policy::Warn => {}
+    }
+
+    let mut data = OverlayData::from(war);
+    data.warnings = violations.iter().map(ToString::to_string).collect();
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::war;
+
+    #[test]
+    fn consistent_wars_pass() {
+        assert!(check(&war(2)).is_empty());
+    }
+
+    #[test]
+    fn finds_each_inconsistency() {
+        let mut war = war(2);
+        war.diff[1] = 5;
+        war.last_diff = Some(7);
+        war.home_score[0] = 60.0;
+        war.tracks.pop();
+        let violations = check(&war);
+        assert!(violations.contains(&Violation::RaceTotal {
+            race: 1,
+            expected: 82.0,
+            actual: 92.0,
+        }));
+        assert!(violations.contains(&Violation::Diff {
+            race: 2,
+            expected: 18,
+            actual: 5,
+        }));
+        assert!(violations.contains(&Violation::LastDiff {
+            expected: Some(5),
+            actual: Some(7),
+        }));
+        assert!(violations.contains(&Violation::Tracks {
+            tracks: 1,
+            races: 2,
+        }));
+    }
+
+    #[test]
+    fn repair_rebuilds_what_the_scores_give() {
+        let mut war = war(2);
+        war.home_score.push(50.0);
+        war.diff = vec![1, 2, 3];
+        war.last_diff = None;
+        war.set_roster(&["Mario".to_string()], &[]);
+        war.roster[0].points.truncate(1);
+        assert!(check(&war).contains(&Violation::LengthMismatch {
+            home: 3,
+            enemy: 2,
+            diff: 3,
+        }));
+
+        repair(&mut war);
+        assert!(check(&war).is_empty());
+        assert_eq!(war.diff, vec![18, 18]);
+        assert_eq!(war.last_diff, Some(18));
+        assert_eq!(war.roster[0].points.len(), 2);
+    }
+
+    #[test]
+    fn policies() {
+        let mut broken = war(2);
+        broken.diff[0] = 0;
+        assert!(matches!(
+            overlay(broken.clone(), Policy::Reject),
+            Err(WarError::Invalid(_))
+        ));
+        assert!(overlay(broken.clone(), Policy::Repair).is_ok());
+        assert!(overlay(broken, Policy::Warn).is_ok());
+    }
+}
@@ -0,0 +1,451 @@
+use crate::format::WarFormat;
+use crate::track::Track;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A war as written by the bot.
+///
+/// Stored documents carry a schema version and are upgraded on read, see
+/// [`migrate`](crate::migrate); this is always the current shape.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct WarData {
+    pub tag: String,
+    pub enemy_tag: String,
+    pub home_score: Vec<f64>,
+    pub enemy_score: Vec<f64>,
+    pub diff: Vec<i32>,
+    pub last_diff: Option<i32>,
+    /// Course of each race, `None` where it wasn't recorded.
+    pub tracks: Vec<Option<Track>>,
+    /// Players of both teams, empty unless someone entered a roster.
+    pub roster: Vec<Player>,
+    /// Every penalty given, oldest first; team penalties are their sums.
+    pub penalties: Vec<Penalty>,
+    /// Ended through the API, regardless of how many races were played.
+    pub finished: bool,
+    pub format: WarFormat,
+    /// Unix seconds; unknown for wars the bot created.
+    pub started_at: Option<u64>,
+}
+
+/// A rostered player and what they scored.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Player {
+    pub name: String,
+    pub team: Team,
+    /// Points per race, `None` where they didn't play or it wasn't entered.
+    pub points: Vec<Option<u32>>,
+}
+
+/// Points taken off a team.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Penalty {
+    pub team: Team,
+    pub amount: i32,
+    /// Races played when it was given, 0 before the first.
+    pub race: u32,
+    /// e.g. "late", "DC", "repick".
+    pub reason: Option<String>,
+    /// Unix seconds; unknown for penalties from before the log existed.
+    pub at: Option<u64>,
+}
+
+impl Penalty {
+    /// A penalty given now, after `race` races.
+    pub fn now(team: Team, amount: i32, race: u32, reason: Option<String>) -> Self {
+        Self {
+            team,
+            amount,
+            race,
+            reason,
+            at: unix_now(),
+        }
+    }
+}
+
+/// Seconds since the Unix epoch, if the clock is sane.
+pub fn unix_now() -> Option<u64> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .ok()
+}
+
+/// One side of a war.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Team {
+    Home,
+    Enemy,
+}
+
+impl WarData {
+    /// A war that hasn't had any races yet.
+    pub fn new(tag: String, enemy_tag: String, format: WarFormat) -> Self {
+        Self {
+            tag,
+            enemy_tag,
+            home_score: Vec::new(),
+            enemy_score: Vec::new(),
+            diff: Vec::new(),
+            last_diff: None,
+            tracks: Vec::new(),
+            roster: Vec::new(),
+            penalties: Vec::new(),
+            finished: false,
+            format,
+            started_at: unix_now(),
+        }
+    }
+
+    pub fn race_count(&self) -> usize {
+        self.diff.len()
+    }
+
+    pub fn push_race(&mut self, home: f64, enemy: f64, track: Option<Track>) {
+        self.home_score.push(home);
+        self.enemy_score.push(enemy);
+        self.tracks.push(track);
+        for player in &mut self.roster {
+            player.points.push(None);
+        }
+        self.diff.push(race_diff(home, enemy));
+        self.last_diff = self.diff.last().copied();
+    }
+
+    /// Replace the scores of race `index` (0-based).
+    pub fn set_race(&mut self, index: usize, home: f64, enemy: f64) {
+        self.home_score[index] = home;
+        self.enemy_score[index] = enemy;
+        self.diff[index] = race_diff(home, enemy);
+        self.last_diff = self.diff.last().copied();
+    }
+
+    /// Drop race `index` (0-based), shifting later races down.
+    pub fn remove_race(&mut self, index: usize) {
+        self.home_score.remove(index);
+        self.enemy_score.remove(index);
+        self.diff.remove(index);
+        if index < self.tracks.len() {
+            self.tracks.remove(index);
+        }
+        for player in &mut self.roster {
+            if index < player.points.len() {
+                player.points.remove(index);
+            }
+        }
+        self.last_diff = self.diff.last().copied();
+    }
+
+    /// Record each rostered player's points in race `index` (0-based);
+    /// `points` follows the roster order.
+    pub fn set_player_points(&mut self, index: usize, points: &[Option<u32>]) {
+        for (player, points) in self.roster.iter_mut().zip(points) {
+            if let Some(slot) = player.points.get_mut(index) {
+                *slot = *points;
+            }
+        }
+    }
+
+    /// Replace the roster, keeping the points of players who stay on it.
+    pub fn set_roster(&mut self, home: &[String], enemy: &[String]) {
+        let races = self.race_count();
+        let mut old = std::mem::take(&mut self.roster);
+        let teams = home
+            .iter()
+            .map(|name| (name, Team::Home))
+            .chain(enemy.iter().map(|name| (name, Team::Enemy)));
+        for (name, team) in teams {
+            let points = match old.iter().position(|p| p.name == *name && p.team == team) {
+                Some(i) => old.swap_remove(i).points,
+                None => vec![None; races],
+            };
+            self.roster.push(Player {
+                name: name.clone(),
+                team,
+                points,
+            });
+        }
+    }
+
+    /// Both teams' points after penalties.
+    pub fn totals(&self) -> (i32, i32) {
+        let home = self.home_score.iter().sum::<f64>().round() as i32;
+        let enemy = self.enemy_score.iter().sum::<f64>().round() as i32;
+        (
+            home - self.penalty(Team::Home),
+            enemy - self.penalty(Team::Enemy),
+        )
+    }
+
+    /// Whether `tag` plays in this war, ignoring ASCII case.
+    pub fn involves(&self, tag: &str) -> bool {
+        self.tag.eq_ignore_ascii_case(tag) || self.enemy_tag.eq_ignore_ascii_case(tag)
+    }
+
+    pub fn phase(&self) -> Phase {
+        let (score, enemy_score) = self.totals();
+        let played = u32::try_from(self.race_count()).unwrap_or(u32::MAX);
+        Phase::of(&self.format, self.finished, played, score - enemy_score)
+    }
+
+    /// Total penalty for `team`.
+    pub fn penalty(&self, team: Team) -> i32 {
+        self.penalties
+            .iter()
+            .filter(|penalty| penalty.team == team)
+            .map(|penalty| penalty.amount)
+            .sum()
+    }
+}
+
+/// Rounded difference between the two teams' points in one race.
+pub fn race_diff(home: f64, enemy: f64) -> i32 {
+    (home - enemy).round() as i32
+}
+
+/// Where a war is in its lifecycle.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Playing the format's regular races.
+    Regulation,
+    /// Regulation ended level; extra races are played until the tie breaks
+    /// or they run out.
+    Tiebreak,
+    /// Decided: finished through the API, or nothing left to play.
+    Final,
+}
+
+impl Phase {
+    /// The phase after `played` races with the totals `diff` apart.
+    fn of(format: &WarFormat, finished: bool, played: u32, diff: i32) -> Self {
+        if finished {
+            Phase::Final
+        } else if played < format.races {
+            Phase::Regulation
+        } else if diff == 0 && played - format.races < format.tiebreak.extra_races {
+            Phase::Tiebreak
+        } else {
+            Phase::Final
+        }
+    }
+}
+
+/// A player's points over the war so far.
+#[derive(Serialize, Clone, PartialEq)]
+pub struct PlayerTotal {
+    pub name: String,
+    pub team: Team,
+    pub score: u32,
+    /// Races with points entered for them.
+    pub races: u32,
+}
+
+/// Which team can no longer be caught.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Clinched {
+    Home,
+    Enemy,
+    None,
+}
+
+/// What overlays and the API see: the war reduced to totals.
+#[derive(Serialize, Clone, PartialEq)]
+pub struct OverlayData {
+    pub tag: String,
+    pub enemy_tag: String,
+    pub score: i32,
+    pub enemy_score: i32,
+    pub diff: i32,
+    pub last_diff: Option<i32>,
+    pub phase: Phase,
+    /// Races left in the current phase, 0 once final.
+    pub race_left: i32,
+    /// Regulation races, for drawing one pip each.
+    pub total_races: u32,
+    /// Extra races played after regulation.
+    pub tiebreak_played: u32,
+    /// Extra races allowed to break a tie.
+    pub tiebreak_races: u32,
+    /// The most `diff` can still move in the races left.
+    pub max_swing_remaining: u32,
+    /// Set once a lead exceeds `max_swing_remaining`, or the war is final.
+    pub clinched: Clinched,
+    /// Average diff per race left the home team needs to win outright,
+    /// rounded up to a tenth. Absent once clinched or nothing is left.
+    pub required_avg_diff: Option<f64>,
+    pub race_diffs: Vec<i32>,
+    /// Course of each race, as stored.
+    pub tracks: Vec<Option<Track>>,
+    /// Rostered players, top scorer first.
+    pub players: Vec<PlayerTotal>,
+    pub home_pen: i32,
+    pub enemy_pen: i32,
+    /// The penalty log, oldest first.
+    pub penalties: Vec<Penalty>,
+    /// Problems found by [`validate`](crate::validate) that were let through.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl From<WarData> for OverlayData {
+    fn from(war_state: WarData) -> Self {
+        let played = u32::try_from(war_state.diff.len()).unwrap_or(u32::MAX);
+        let home_pen = war_state.penalty(Team::Home);
+        let enemy_pen = war_state.penalty(Team::Enemy);
+        let (score, enemy_score) = war_state.totals();
+        let diff = score - enemy_score;
+        let last_diff = war_state.diff.iter().last().copied();
+        let format = &war_state.format;
+        let phase = war_state.phase();
+        let tiebreak_played = played.saturating_sub(format.races);
+        let race_left = match phase {
+            Phase::Regulation => format.races - played,
+            Phase::Tiebreak => format.tiebreak.extra_races - tiebreak_played,
+            Phase::Final => 0,
+        } as i32;
+        let max_swing_remaining = race_left as u32 * format.max_race_swing();
+        let clinched = match diff.unsigned_abs() {
+            lead if lead <= max_swing_remaining && phase != Phase::Final => Clinched::None,
+            _ if diff > 0 => Clinched::Home,
+            _ if diff < 0 => Clinched::Enemy,
+            _ => Clinched::None,
+        };
+        let required_avg_diff = (clinched == Clinched::None && race_left > 0)
+            .then(|| (f64::from(1 - diff) / f64::from(race_left) * 10.0).ceil() / 10.0);
+        let total_races = format.races;
+        let mut players: Vec<PlayerTotal> = war_state
+            .roster
+            .iter()
+            .map(|player| PlayerTotal {
+                name: player.name.clone(),
+                team: player.team,
+                score: player.points.iter().flatten().sum(),
+                races: player.points.iter().flatten().count() as u32,
+            })
+            .collect();
+        players.sort_by_key(|player| std::cmp::Reverse(player.score));
+        let tiebreak_races = format.tiebreak.extra_races;
+
+        OverlayData {
+            tag: war_state.tag,
+            enemy_tag: war_state.enemy_tag,
+            score,
+            enemy_score,
+            diff,
+            last_diff,
+            phase,
+            race_left,
+            total_races,
+            tiebreak_played,
+            tiebreak_races,
+            max_swing_remaining,
+            clinched,
+            required_avg_diff,
+            race_diffs: war_state.diff,
+            tracks: war_state.tracks,
+            players,
+            home_pen,
+            enemy_pen,
+            penalties: war_state.penalties,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{races, war, war_in};
+
+    fn overlay(war: WarData) -> OverlayData {
+        OverlayData::from(war)
+    }
+
+    #[test]
+    fn regulation_counts_down() {
+        let data = overlay(war(3));
+        assert_eq!(data.phase, Phase::Regulation);
+        assert_eq!(data.race_left, 9);
+        assert_eq!(data.total_races, 12);
+        assert_eq!(data.tiebreak_played, 0);
+        assert_eq!(overlay(war(12)).phase, Phase::Final);
+    }
+
+    #[test]
+    fn ties_go_to_extra_races() {
+        let mut war = war_in(races(2), 1);
+        war.push_race(32.0, 50.0, None);
+        let data = overlay(war.clone());
+        assert_eq!(data.phase, Phase::Tiebreak);
+        assert_eq!((data.race_left, data.tiebreak_races), (4, 4));
+
+        war.push_race(41.0, 41.0, None);
+        let data = overlay(war.clone());
+        assert_eq!(data.phase, Phase::Tiebreak);
+        assert_eq!((data.race_left, data.tiebreak_played), (3, 1));
+
+        war.push_race(42.0, 40.0, None);
+        assert_eq!(overlay(war).phase, Phase::Final);
+    }
+
+    #[test]
+    fn tiebreaks_run_out() {
+        let mut war = war_in(races(1), 0);
+        for _ in 0..5 {
+            assert_ne!(war.phase(), Phase::Final);
+            war.push_race(41.0, 41.0, None);
+        }
+        assert_eq!(war.phase(), Phase::Final);
+        assert_eq!(overlay(war).race_left, 0);
+    }
+
+    #[test]
+    fn finishing_ends_the_war_early() {
+        let mut war = war(3);
+        war.finished = true;
+        let data = overlay(war);
+        assert_eq!(data.phase, Phase::Final);
+        assert_eq!(data.race_left, 0);
+    }
+
+    #[test]
+    fn leads_clinch_once_out_of_reach() {
+        assert_eq!(overlay(war(12)).clinched, Clinched::Home);
+        let mut war = war_in(races(2), 0);
+        war.push_race(61.0, 21.0, None);
+        let data = overlay(war.clone());
+        assert_eq!(data.max_swing_remaining, 40);
+        assert_eq!(data.clinched, Clinched::None, "a tie is still possible");
+
+        let mut war = war_in(races(2), 0);
+        war.push_race(62.0, 20.0, None);
+        assert_eq!(overlay(war).clinched, Clinched::Home);
+
+        let mut war = war_in(races(2), 0);
+        war.push_race(20.0, 62.0, None);
+        assert_eq!(overlay(war).clinched, Clinched::Enemy);
+    }
+
+    #[test]
+    fn required_average_rounds_up_to_a_tenth() {
+        assert_eq!(overlay(war(0)).required_avg_diff, Some(0.1));
+        assert_eq!(overlay(war(3)).required_avg_diff, Some(-5.8));
+
+        let mut war = war_in(races(2), 0);
+        war.push_race(61.0, 21.0, None);
+        assert_eq!(overlay(war.clone()).required_avg_diff, Some(-39.0));
+        war.push_race(21.0, 61.0, None);
+        assert_eq!(overlay(war).required_avg_diff, Some(0.3));
+    }
+
+    #[test]
+    fn nothing_is_required_once_decided() {
+        assert_eq!(overlay(war(12)).required_avg_diff, None);
+        let mut war = war_in(races(2), 0);
+        war.push_race(62.0, 20.0, None);
+        assert_eq!(overlay(war).required_avg_diff, None);
+    }
+}